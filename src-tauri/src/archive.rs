@@ -1,93 +1,449 @@
-use crate::commands::{ArchiveOptions, ConvertResult};
+use crate::commands::{
+    ArchiveEntry, ArchiveEntryTest, ArchiveListing, ArchiveOptions, ArchiveTestReport, CompressOptions,
+    ConvertResult, ExtractOptions,
+};
 use crate::config::{self, AppConfig};
-use std::path::Path;
+use codec::Codec;
+use guard::Guard;
+use glob::{MatchOptions, Pattern};
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
 use std::fs;
+use std::io::{self, Read, Write};
+use std::time::SystemTime;
 
-/// Extract archive (ZIP, 7z, RAR)
+mod codec;
+mod guard;
+mod rar;
+mod sevenz;
+mod tarball;
+mod zipfile;
+
+/// Archive formats that can be read, as told by the file name
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Zip,
+    SevenZ,
+    Rar,
+    Tar(Option<Codec>),
+}
+
+impl Format {
+    /// The format of an archive, and its name with the extension taken off
+    fn detect(path: &str) -> Result<(Self, String), String> {
+        let file_path = Path::new(path);
+        if let Some(tarball) = file_path.file_name()
+            .and_then(|s| s.to_str())
+            .and_then(tarball::Tarball::detect)
+        {
+            return Ok((Format::Tar(tarball.codec), tarball.stem));
+        }
+
+        let stem = file_path.file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("extracted")
+            .to_string();
+        let extension = file_path.extension()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let format = match extension.as_str() {
+            "zip" => Format::Zip,
+            "7z" => Format::SevenZ,
+            ext if rar::is_rar_extension(ext) => Format::Rar,
+            _ => return Err(format!("Unsupported archive format: {}", extension)),
+        };
+        Ok((format, stem))
+    }
+
+    fn name(self) -> String {
+        match self {
+            Format::Zip => "zip".to_string(),
+            Format::SevenZ => "7z".to_string(),
+            Format::Rar => "rar".to_string(),
+            Format::Tar(None) => "tar".to_string(),
+            Format::Tar(Some(codec)) => format!("tar.{}", codec.extension()),
+        }
+    }
+}
+
+/// Extract archive (ZIP, 7z, RAR, tar), either whole or only the entries
+/// chosen in `options`
 pub async fn extract(
     config: &AppConfig,
     path: &str,
     password: Option<String>,
+    options: &ExtractOptions,
 ) -> Result<ConvertResult, String> {
     let output_folder = config::get_output_path(config, "Archives")?;
-    
-    let file_path = Path::new(path);
-    let stem = file_path.file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("extracted");
+    let (format, stem) = Format::detect(path)?;
+    let mut selection = Selection::new(&options.entries, options.flatten);
+    let password = password.as_deref().filter(|p| !p.is_empty());
     
     let extract_folder = output_folder.join(stem);
+    let mut guard = Guard::new(&config.extraction_limits, Path::new(path), &extract_folder);
+    // Tarballs have no index, and would have to be decompressed twice
+    if !matches!(format, Format::Tar(_)) {
+        let listing = list(path, password)?;
+        guard.check_declared(&declared_entries(&listing.entries, &selection))?;
+    }
     fs::create_dir_all(&extract_folder)
         .map_err(|e| format!("Failed to create extract folder: {}", e))?;
     
-    let extension = file_path.extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("")
-        .to_lowercase();
-    
-    match extension.as_str() {
-        "zip" => extract_zip(path, &extract_folder, password)?,
-        "7z" => extract_7z(path, &extract_folder, password)?,
-        "rar" => extract_rar(path, &extract_folder, password)?,
-        _ => return Err(format!("Unsupported archive format: {}", extension)),
+    let result = match format {
+        Format::Zip => zipfile::extract(path, &extract_folder, password, &mut selection, &mut guard),
+        Format::SevenZ => sevenz::extract(path, &extract_folder, password, &mut selection, &mut guard),
+        Format::Rar => rar::extract(path, &extract_folder, password, &mut selection, &mut guard),
+        Format::Tar(codec) => tarball::extract(path, &extract_folder, codec, &mut selection, &mut guard),
+    };
+    if let Err(message) = result {
+        if guard.tripped() {
+            guard.clean_up();
+        }
+        return Err(message);
+    }
+
+    if selection.is_partial() && selection.files == 0 {
+        // Only removed when nothing was extracted into it before
+        let _ = fs::remove_dir(&extract_folder);
+        return Err(format!("No entries match: {}", options.entries.join(", ")));
     }
     
     Ok(ConvertResult {
         success: true,
         output_files: vec![extract_folder.to_string_lossy().to_string()],
         output_folder: output_folder.to_string_lossy().to_string(),
-        message: "展開が完了しました".to_string(),
+        message: if selection.is_partial() {
+            format!("{}個のファイルを展開しました", selection.files)
+        } else {
+            "展開が完了しました".to_string()
+        },
     })
 }
 
-fn extract_zip(path: &str, output: &Path, _password: Option<String>) -> Result<(), String> {
-    let file = fs::File::open(path)
-        .map_err(|e| format!("Failed to open zip file: {}", e))?;
-    
-    let mut archive = zip::ZipArchive::new(file)
-        .map_err(|e| format!("Failed to read zip archive: {}", e))?;
-    
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)
-            .map_err(|e| format!("Failed to read zip entry: {}", e))?;
-        
-        let outpath = match file.enclosed_name() {
-            Some(path) => output.join(path),
-            None => continue,
-        };
-        
-        if file.name().ends_with('/') {
-            fs::create_dir_all(&outpath)
-                .map_err(|e| format!("Failed to create directory: {}", e))?;
-        } else {
-            if let Some(parent) = outpath.parent() {
-                if !parent.exists() {
-                    fs::create_dir_all(parent)
-                        .map_err(|e| format!("Failed to create parent directory: {}", e))?;
-                }
+/// The entries an extraction would write, as paths in the output folder,
+/// whether each is a folder, and its size
+fn declared_entries(entries: &[ArchiveEntry], selection: &Selection) -> Vec<(PathBuf, bool, u64)> {
+    entries.iter()
+        .filter(|entry| !(selection.flatten && entry.is_dir))
+        .filter_map(|entry| {
+            let relative = enclosed_path(&entry.path)?;
+            if !selection.selects(&relative) {
+                return None;
+            }
+            let relative = match selection.flatten {
+                true => PathBuf::from(relative.file_name()?),
+                false => relative,
+            };
+            Some((relative, entry.is_dir, entry.size))
+        })
+        .collect()
+}
+
+/// List what an archive holds without extracting it. Folders only implied
+/// by the paths of their contents are listed too, so the whole tree can be
+/// shown. A password is needed only where names are encrypted.
+pub fn list(path: &str, password: Option<&str>) -> Result<ArchiveListing, String> {
+    let (format, _) = Format::detect(path)?;
+    let mut entries = match format {
+        Format::Zip => zipfile::list(path)?,
+        Format::SevenZ => sevenz::list(path, password)?,
+        Format::Rar => rar::list(path, password)?,
+        Format::Tar(codec) => tarball::list(path, codec)?,
+    };
+
+    for entry in &mut entries {
+        entry.path = entry.path.replace('\\', "/").trim_end_matches('/').to_string();
+    }
+    let known: HashSet<String> = entries.iter().map(|entry| entry.path.clone()).collect();
+    let implied: BTreeSet<String> = entries.iter()
+        .flat_map(|entry| {
+            entry.path.match_indices('/').map(|(end, _)| entry.path[..end].to_string()).collect::<Vec<_>>()
+        })
+        .filter(|folder| !folder.is_empty() && !known.contains(folder))
+        .collect();
+    entries.extend(implied.into_iter().map(|path| ArchiveEntry {
+        path,
+        is_dir: true,
+        ..Default::default()
+    }));
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let files = entries.iter().filter(|entry| !entry.is_dir);
+    Ok(ArchiveListing {
+        path: path.to_string(),
+        format: format.name(),
+        file_count: files.clone().count(),
+        total_size: files.map(|entry| entry.size).sum(),
+        entries,
+    })
+}
+
+/// Check an archive without extracting it: every file is decompressed and
+/// thrown away, which verifies its CRC where the format keeps one. Tarballs
+/// have none per file, but gzip, bzip2, xz and zstd check the whole stream.
+pub fn test(path: &str, password: Option<&str>) -> Result<ArchiveTestReport, String> {
+    let (format, _) = Format::detect(path)?;
+    let run = match format {
+        Format::Zip => zipfile::test(path, password)?,
+        Format::SevenZ => sevenz::test(path, password)?,
+        Format::Rar => rar::test(path, password)?,
+        Format::Tar(codec) => tarball::test(path, codec)?,
+    };
+
+    // Some formats cannot tell a wrong key from damaged data
+    let mut encrypted = run.entries.iter().filter(|entry| entry.encrypted).peekable();
+    if password.is_some() && encrypted.peek().is_some() && !encrypted.any(|entry| entry.passed) {
+        return Err(wrong_password(path));
+    }
+
+    let failed = run.entries.iter().filter(|entry| !entry.passed).count();
+    Ok(ArchiveTestReport {
+        path: path.to_string(),
+        format: format.name(),
+        passed: run.entries.len() - failed,
+        failed,
+        intact: failed == 0 && run.stopped.is_none(),
+        error: run.stopped,
+        entries: run.entries,
+    })
+}
+
+/// Files checked by a test, and the damage that kept it from reaching the
+/// end of the archive
+#[derive(Default)]
+struct TestRun {
+    entries: Vec<ArchiveEntryTest>,
+    stopped: Option<String>,
+}
+
+impl TestRun {
+    fn record(&mut self, path: String, size: u64, encrypted: bool, result: Result<(), String>) {
+        self.entries.push(ArchiveEntryTest {
+            path,
+            size,
+            encrypted,
+            passed: result.is_ok(),
+            error: result.err(),
+        });
+    }
+}
+
+/// An MS-DOS date and time, as kept by ZIP and RAR, in local time with no
+/// zone recorded
+fn dos_timestamp(value: u32) -> Option<String> {
+    let (date, time) = (value >> 16, value & 0xFFFF);
+    let (month, day) = ((date >> 5) & 0x0F, date & 0x1F);
+    if month == 0 || day == 0 {
+        return None;
+    }
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        (date >> 9) + 1980,
+        month,
+        day,
+        time >> 11,
+        (time >> 5) & 0x3F,
+        (time & 0x1F) * 2
+    ))
+}
+
+fn system_timestamp(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Local>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
+}
+
+/// Relative path of an archive entry inside the extraction folder, or None
+/// when its name would escape that folder
+fn enclosed_path(name: &str) -> Option<PathBuf> {
+    if name.contains('\0') {
+        return None;
+    }
+    // Archives made on Windows may separate with backslashes
+    let name = name.replace('\\', "/");
+    if name.starts_with('/') {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    for part in name.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
             }
-            let mut outfile = fs::File::create(&outpath)
-                .map_err(|e| format!("Failed to create file: {}", e))?;
-            std::io::copy(&mut file, &mut outfile)
-                .map_err(|e| format!("Failed to write file: {}", e))?;
+            // Drive letters and alternate data streams
+            part if part.contains(':') => return None,
+            part => parts.push(part),
         }
     }
-    
-    Ok(())
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.iter().collect())
+    }
 }
 
-fn extract_7z(_path: &str, _output: &Path, _password: Option<String>) -> Result<(), String> {
-    // TODO: Implement using sevenz-rust crate
-    Ok(())
+/// Entries chosen for extraction, by path or glob pattern. A path takes a
+/// folder with everything in it; patterns match as in `SourceFilter`. With
+/// nothing chosen the whole archive is extracted.
+struct Selection {
+    selectors: Vec<Selector>,
+    flatten: bool,
+    /// Flattened names handed out so far, lowercased since Windows file
+    /// names ignore case
+    taken: HashSet<String>,
+    /// Files extracted so far
+    files: usize,
 }
 
-fn extract_rar(_path: &str, _output: &Path, _password: Option<String>) -> Result<(), String> {
-    // TODO: Implement using unrar crate
-    // Note: Requires unrar library installed on system
-    Ok(())
+/// A selector is tried as a glob pattern and as a plain path, so that
+/// names with brackets in them can be chosen as they are
+struct Selector {
+    path: String,
+    pattern: Option<Pattern>,
+}
+
+impl Selection {
+    fn new(entries: &[String], flatten: bool) -> Self {
+        let selectors = entries.iter()
+            .map(|entry| entry.trim().replace('\\', "/").trim_matches('/').to_string())
+            .filter(|entry| !entry.is_empty())
+            .map(|path| Selector { pattern: Pattern::new(&path).ok(), path })
+            .collect();
+        Self {
+            selectors,
+            flatten,
+            taken: HashSet::new(),
+            files: 0,
+        }
+    }
+
+    fn is_partial(&self) -> bool {
+        !self.selectors.is_empty()
+    }
+
+    /// Where an entry goes, relative to the extraction folder, or None when
+    /// it is not chosen or its name would escape the folder. Flattening
+    /// puts every file straight into the folder, numbering clashing names,
+    /// and leaves folders out.
+    fn target(&mut self, name: &str, is_dir: bool) -> Option<PathBuf> {
+        let relative = enclosed_path(name)?;
+        if !self.selects(&relative) {
+            return None;
+        }
+        if is_dir {
+            return (!self.flatten).then_some(relative);
+        }
+
+        self.files += 1;
+        if !self.flatten {
+            return Some(relative);
+        }
+        let file_name = relative.file_name()?.to_string_lossy().to_string();
+        let (stem, extension) = match file_name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => (stem.to_string(), format!(".{}", extension)),
+            _ => (file_name.clone(), String::new()),
+        };
+        let unique = (1..)
+            .map(|n| match n {
+                1 => file_name.clone(),
+                n => format!("{} ({}){}", stem, n, extension),
+            })
+            .find(|candidate| self.taken.insert(candidate.to_lowercase()))?;
+        Some(PathBuf::from(unique))
+    }
+
+    /// Whether an entry is chosen, itself or through a folder it is in
+    fn selects(&self, relative: &Path) -> bool {
+        if self.selectors.is_empty() {
+            return true;
+        }
+        let path = relative.to_string_lossy().replace('\\', "/");
+        let mut candidates = path.match_indices('/')
+            .map(|(end, _)| &path[..end])
+            .chain([path.as_str()]);
+        let selected = candidates.any(|candidate| {
+            self.selectors.iter().any(|selector| {
+                selector.path.eq_ignore_ascii_case(candidate)
+                    || selector.pattern.as_ref().is_some_and(|pattern| pattern_matches(pattern, candidate))
+            })
+        });
+        selected
+    }
+}
+
+fn password_required(path: &str) -> String {
+    format!("Encrypted archive requires a password: {}", path)
+}
+
+fn wrong_password(path: &str) -> String {
+    format!("Wrong password for archive: {}", path)
 }
 
-/// Create archive (ZIP, 7z)
+/// Check that writing `target` stays inside `output` on disk. Entry names
+/// are checked when read, but a link already in the folder, such as one
+/// left by an earlier archive, could still lead the write elsewhere.
+fn stays_inside(output: &Path, target: &Path) -> Result<(), String> {
+    let outside = || format!("Entry leads outside the extract folder: {}", target.display());
+    // A dangling link would be followed to wherever it points
+    if fs::symlink_metadata(target).is_ok_and(|metadata| metadata.file_type().is_symlink()) && !target.exists() {
+        return Err(outside());
+    }
+
+    let output = output.canonicalize()
+        .map_err(|e| format!("Failed to open extract folder: {}", e))?;
+    let inside = target.ancestors()
+        .find(|path| path.exists())
+        .and_then(|path| path.canonicalize().ok())
+        .is_some_and(|path| path.starts_with(&output));
+    if inside {
+        Ok(())
+    } else {
+        Err(outside())
+    }
+}
+
+/// Failure extracting an entry: reading the archive, which may mean a wrong
+/// password, or writing the output
+enum EntryError {
+    Read(io::Error),
+    Write(String),
+}
+
+fn write_entry(
+    output: &Path,
+    target: &Path,
+    is_directory: bool,
+    data: &mut dyn Read,
+    guard: &mut Guard,
+) -> Result<(), EntryError> {
+    stays_inside(output, target).map_err(EntryError::Write)?;
+    if is_directory {
+        return fs::create_dir_all(target)
+            .map_err(|e| EntryError::Write(format!("Failed to create directory: {}", e)));
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| EntryError::Write(format!("Failed to create parent directory: {}", e)))?;
+    }
+    let mut file = fs::File::create(target)
+        .map_err(|e| EntryError::Write(format!("Failed to create file: {}", e)))?;
+
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = data.read(&mut buffer).map_err(EntryError::Read)?;
+        if read == 0 {
+            return Ok(());
+        }
+        file.write_all(&buffer[..read])
+            .map_err(|e| EntryError::Write(format!("Failed to write file: {}", e)))?;
+        guard.add(read as u64).map_err(EntryError::Write)?;
+    }
+}
+
+/// Create archive (ZIP, 7z, tar)
 pub async fn create(
     config: &AppConfig,
     paths: &[String],
@@ -98,6 +454,7 @@ pub async fn create(
     
     let extension = match options.format.as_str() {
         "7z" => "7z",
+        format if tarball::Tarball::detect(&format!(".{}", format)).is_some() => format,
         _ => "zip",
     };
     
@@ -108,56 +465,254 @@ pub async fn create(
     };
     
     let output_path = output_folder.join(&output_filename);
+    let filter = SourceFilter::new(&options.include, &options.exclude)?;
+    let sources = collect_sources(paths, &filter)?;
+    let password = options.password.as_deref().filter(|p| !p.is_empty());
     
     match options.format.as_str() {
-        "zip" => create_zip(paths, &output_path, &options.password)?,
-        "7z" => create_7z(paths, &output_path, &options.password)?,
-        _ => return Err(format!("Unsupported format: {}", options.format)),
+        "zip" => zipfile::create(&sources, &output_path, password)?,
+        "7z" => sevenz::create(&sources, &output_path, password, options)?,
+        _ => match tarball::Tarball::detect(&output_filename) {
+            Some(tarball) => tarball::create(&sources, &output_path, tarball.codec, options.level)?,
+            None => return Err(format!("Unsupported format: {}", options.format)),
+        },
     }
     
     Ok(ConvertResult {
         success: true,
         output_files: vec![output_path.to_string_lossy().to_string()],
         output_folder: output_folder.to_string_lossy().to_string(),
+        message: format!("{}個のファイルを圧縮しました", sources.iter().filter(|source| !source.is_dir).count()),
+    })
+}
+
+/// Compress each file on its own into a single-file format (gz, bz2, xz,
+/// zst, lz4), without a container around it
+pub async fn compress(
+    config: &AppConfig,
+    paths: &[String],
+    options: &CompressOptions,
+) -> Result<ConvertResult, String> {
+    let codec = Codec::from_extension(&options.format)
+        .ok_or_else(|| format!("Unsupported format: {}", options.format))?;
+    let level = codec.level(options.level)?;
+    let threads = match options.threads {
+        Some(0) => return Err("Thread count must be at least 1".to_string()),
+        Some(threads) => threads,
+        None => codec::default_threads(),
+    };
+    let output_folder = config::get_output_path(config, "Archives")?;
+
+    let mut output_files = Vec::new();
+    for path in paths {
+        let input = Path::new(path);
+        if !input.is_file() {
+            return Err(format!("Not a file: {}", path));
+        }
+        let name = input.file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("file");
+        let output_path = output_folder.join(format!("{}.{}", name, codec.extension()));
+        codec::compress_file(input, &output_path, codec, level, threads)?;
+        output_files.push(output_path.to_string_lossy().to_string());
+    }
+
+    Ok(ConvertResult {
+        success: true,
+        output_files,
+        output_folder: output_folder.to_string_lossy().to_string(),
         message: format!("{}個のファイルを圧縮しました", paths.len()),
     })
 }
 
-fn create_zip(paths: &[String], output: &Path, _password: &Option<String>) -> Result<(), String> {
-    let file = fs::File::create(output)
-        .map_err(|e| format!("Failed to create zip file: {}", e))?;
-    
-    let mut zip = zip::ZipWriter::new(file);
-    let options = zip::write::SimpleFileOptions::default()
-        .compression_method(zip::CompressionMethod::Deflated);
-    
+/// Decompress single-file formats, each into a file named without the
+/// compression extension
+pub async fn decompress(config: &AppConfig, paths: &[String]) -> Result<ConvertResult, String> {
+    let output_folder = config::get_output_path(config, "Archives")?;
+
+    let mut output_files = Vec::new();
     for path in paths {
-        let file_path = Path::new(path);
-        
-        if file_path.is_file() {
-            let file_name = file_path.file_name()
-                .and_then(|s| s.to_str())
-                .unwrap_or("file");
-            
-            zip.start_file(file_name, options)
-                .map_err(|e| format!("Failed to start zip entry: {}", e))?;
-            
-            let content = fs::read(path)
-                .map_err(|e| format!("Failed to read file: {}", e))?;
-            
-            use std::io::Write;
-            zip.write_all(&content)
-                .map_err(|e| format!("Failed to write to zip: {}", e))?;
+        let input = Path::new(path);
+        let codec = input.extension()
+            .and_then(|s| s.to_str())
+            .and_then(Codec::from_extension)
+            .ok_or_else(|| format!("Unsupported compressed file: {}", path))?;
+        let stem = input.file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("decompressed");
+        let output_path = output_folder.join(stem);
+        let mut guard = Guard::new(&config.extraction_limits, input, &output_folder);
+        codec::decompress_file(input, &output_path, codec, &mut guard)?;
+        output_files.push(output_path.to_string_lossy().to_string());
+    }
+
+    Ok(ConvertResult {
+        success: true,
+        output_files,
+        output_folder: output_folder.to_string_lossy().to_string(),
+        message: format!("{}個のファイルを展開しました", paths.len()),
+    })
+}
+
+/// A file or folder to archive, with the name it gets in the archive
+struct Source {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+}
+
+/// Each input under its own name, folders with everything beneath them,
+/// empty ones included, less what the filter leaves out
+fn collect_sources(paths: &[String], filter: &SourceFilter) -> Result<Vec<Source>, String> {
+    let mut sources = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        let name = path.file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("file");
+        add_source(path, name.to_string(), filter, &mut sources)?;
+    }
+
+    // With include patterns, only folders leading to an included file remain
+    if !filter.include.is_empty() {
+        let files: Vec<String> = sources.iter()
+            .filter(|source| !source.is_dir)
+            .map(|source| source.name.clone())
+            .collect();
+        sources.retain(|source| {
+            !source.is_dir || files.iter().any(|file| file.starts_with(&format!("{}/", source.name)))
+        });
+    }
+    Ok(sources)
+}
+
+fn add_source(path: &Path, name: String, filter: &SourceFilter, sources: &mut Vec<Source>) -> Result<(), String> {
+    if filter.excludes(&name) {
+        return Ok(());
+    }
+
+    // Links to folders are not followed, so that a loop cannot recurse forever
+    let is_dir = fs::symlink_metadata(path)
+        .map_err(|e| format!("Failed to read file info: {}", e))?
+        .is_dir();
+    if !is_dir {
+        if path.is_file() && filter.includes(&name) {
+            sources.push(Source { path: path.to_path_buf(), name, is_dir: false });
         }
+        return Ok(());
+    }
+
+    let mut children: Vec<PathBuf> = fs::read_dir(path)
+        .map_err(|e| format!("Failed to read folder: {}", e))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to read folder: {}", e))?;
+    children.sort();
+
+    sources.push(Source { path: path.to_path_buf(), name: name.clone(), is_dir: true });
+    for child in children {
+        let child_name = child.file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        add_source(&child, format!("{}/{}", name, child_name), filter, sources)?;
     }
-    
-    zip.finish()
-        .map_err(|e| format!("Failed to finish zip: {}", e))?;
-    
     Ok(())
 }
 
-fn create_7z(_paths: &[String], _output: &Path, _password: &Option<String>) -> Result<(), String> {
-    // TODO: Implement using sevenz-rust crate
-    Ok(())
+/// Glob patterns choosing what goes into an archive. A pattern without a
+/// slash, such as `node_modules` or `*.tmp`, is matched against the name of
+/// each file and folder; one with a slash against its whole path in the
+/// archive. Matching ignores case, as Windows file names do.
+struct SourceFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl SourceFilter {
+    fn new(include: &[String], exclude: &[String]) -> Result<Self, String> {
+        let compile = |patterns: &[String]| {
+            patterns.iter()
+                .map(|pattern| pattern.trim())
+                .filter(|pattern| !pattern.is_empty())
+                .map(|pattern| Pattern::new(pattern).map_err(|e| format!("Invalid pattern {}: {}", pattern, e)))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// Excluded files and folders are left out along with all they contain
+    fn excludes(&self, name: &str) -> bool {
+        self.exclude.iter().any(|pattern| pattern_matches(pattern, name))
+    }
+
+    /// Whether a file is wanted; include patterns apply to files only
+    fn includes(&self, name: &str) -> bool {
+        self.include.is_empty() || self.include.iter().any(|pattern| pattern_matches(pattern, name))
+    }
+
+}
+
+fn pattern_matches(pattern: &Pattern, name: &str) -> bool {
+    let options = MatchOptions {
+        case_sensitive: false,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    if pattern.as_str().contains('/') {
+        pattern.matches_with(name, options)
+    } else {
+        let file_name = name.rsplit('/').next().unwrap_or(name);
+        pattern.matches_with(file_name, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enclosed_path_keeps_names_inside() {
+        assert_eq!(enclosed_path("docs/a.txt"), Some(PathBuf::from("docs/a.txt")));
+        assert_eq!(enclosed_path("docs\\a.txt"), Some(PathBuf::from("docs/a.txt")));
+        assert_eq!(enclosed_path("./a/./b/../c/"), Some(PathBuf::from("a/c")));
+        assert_eq!(enclosed_path("a/../../x"), None);
+        assert_eq!(enclosed_path("..\\x"), None);
+        assert_eq!(enclosed_path("/etc/passwd"), None);
+        assert_eq!(enclosed_path("C:/Windows"), None);
+        assert_eq!(enclosed_path("file.txt:stream"), None);
+        assert_eq!(enclosed_path("a\0b"), None);
+        assert_eq!(enclosed_path("."), None);
+    }
+
+    #[test]
+    fn flattening_numbers_clashing_names() {
+        let mut selection = Selection::new(&[], true);
+        assert_eq!(selection.target("docs", true), None);
+        assert_eq!(selection.target("docs/a.txt", false), Some(PathBuf::from("a.txt")));
+        assert_eq!(selection.target("other/a.txt", false), Some(PathBuf::from("a (2).txt")));
+        assert_eq!(selection.target("third/A.TXT", false), Some(PathBuf::from("A (3).TXT")));
+        assert_eq!(selection.target(".profile", false), Some(PathBuf::from(".profile")));
+        assert_eq!(selection.target("home/.profile", false), Some(PathBuf::from(".profile (2)")));
+        assert_eq!(selection.target("../escape.txt", false), None);
+        assert_eq!(selection.files, 5);
+    }
+
+    #[test]
+    fn selection_takes_folders_and_patterns() {
+        let mut selection = Selection::new(&["docs".to_string(), "*.pdf".to_string()], false);
+        assert_eq!(selection.target("docs/sub/a.txt", false), Some(PathBuf::from("docs/sub/a.txt")));
+        assert_eq!(selection.target("report.pdf", false), Some(PathBuf::from("report.pdf")));
+        assert_eq!(selection.target("other/a.txt", false), None);
+    }
+
+    #[test]
+    fn dos_timestamps() {
+        let date = (2024 - 1980) << 9 | 3 << 5 | 15;
+        let time = 13 << 11 | 45 << 5 | 15;
+        assert_eq!(dos_timestamp(date << 16 | time), Some("2024-03-15T13:45:30".to_string()));
+        assert_eq!(dos_timestamp(0), None);
+    }
 }
@@ -0,0 +1,247 @@
+use super::guard::{Guard, GuardedWriter};
+use bzip2::bufread::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::thread;
+use xz2::bufread::XzDecoder;
+use xz2::stream::{Check, MtStreamBuilder};
+use xz2::write::XzEncoder;
+
+/// Stream compression formats, used on a single file or around a tarball
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+    Lz4,
+}
+
+impl Codec {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "gz" => Some(Codec::Gzip),
+            "bz2" => Some(Codec::Bzip2),
+            "xz" => Some(Codec::Xz),
+            "zst" => Some(Codec::Zstd),
+            "lz4" => Some(Codec::Lz4),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gz",
+            Codec::Bzip2 => "bz2",
+            Codec::Xz => "xz",
+            Codec::Zstd => "zst",
+            Codec::Lz4 => "lz4",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+            Codec::Bzip2 => "bzip2",
+            Codec::Xz => "xz",
+            Codec::Zstd => "zstd",
+            Codec::Lz4 => "lz4",
+        }
+    }
+
+    /// Levels accepted, and the one used when none is chosen, as in each
+    /// format's own command-line tool
+    fn levels(self) -> (RangeInclusive<u32>, u32) {
+        match self {
+            Codec::Gzip => (0..=9, 6),
+            Codec::Bzip2 => (1..=9, 9),
+            Codec::Xz => (0..=9, 6),
+            Codec::Zstd => (1..=22, 3),
+            Codec::Lz4 => (1..=12, 1),
+        }
+    }
+
+    /// The level to compress at, checked against what the format accepts
+    pub fn level(self, level: Option<u32>) -> Result<u32, String> {
+        let (range, default) = self.levels();
+        match level {
+            None => Ok(default),
+            Some(level) if range.contains(&level) => Ok(level),
+            Some(_) => Err(format!(
+                "Compression level for {} must be between {} and {}",
+                self.name(),
+                range.start(),
+                range.end()
+            )),
+        }
+    }
+
+    /// Concatenated streams, as made by `cat a.gz b.gz`, are read through,
+    /// except with lz4
+    pub fn decoder<'a, R: BufRead + 'a>(self, reader: R) -> Result<Box<dyn Read + 'a>, String> {
+        Ok(match self {
+            Codec::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Codec::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+            Codec::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+            Codec::Zstd => Box::new(
+                zstd::stream::read::Decoder::with_buffer(reader)
+                    .map_err(|e| format!("Failed to start zstd decoder: {}", e))?,
+            ),
+            Codec::Lz4 => Box::new(Lz4Reader(Some(
+                lz4::Decoder::new(reader)
+                    .map_err(|e| format!("Failed to start lz4 decoder: {}", e))?,
+            ))),
+        })
+    }
+
+    /// xz and zstd split the work over `threads`; the other formats always
+    /// run on one
+    pub fn encoder<W: Write>(self, writer: W, level: u32, threads: u32) -> Result<Encoder<W>, String> {
+        Ok(match self {
+            Codec::Gzip => Encoder::Gzip(GzEncoder::new(writer, flate2::Compression::new(level))),
+            Codec::Bzip2 => Encoder::Bzip2(BzEncoder::new(writer, bzip2::Compression::new(level))),
+            Codec::Xz if threads > 1 => {
+                let stream = MtStreamBuilder::new()
+                    .threads(threads)
+                    .preset(level)
+                    .check(Check::Crc64)
+                    .encoder()
+                    .map_err(|e| format!("Failed to start xz encoder: {}", e))?;
+                Encoder::Xz(XzEncoder::new_stream(writer, stream))
+            }
+            Codec::Xz => Encoder::Xz(XzEncoder::new(writer, level)),
+            Codec::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(writer, level as i32)
+                    .map_err(|e| format!("Failed to start zstd encoder: {}", e))?;
+                if threads > 1 {
+                    encoder.multithread(threads)
+                        .map_err(|e| format!("Failed to start zstd encoder: {}", e))?;
+                }
+                Encoder::Zstd(encoder)
+            }
+            Codec::Lz4 => Encoder::Lz4(
+                lz4::EncoderBuilder::new()
+                    .level(level)
+                    .build(writer)
+                    .map_err(|e| format!("Failed to start lz4 encoder: {}", e))?,
+            ),
+        })
+    }
+}
+
+/// Threads to compress with when none are chosen: one per CPU
+pub fn default_threads() -> u32 {
+    thread::available_parallelism().map_or(1, |threads| threads.get() as u32)
+}
+
+/// Compress a single file into `output`
+pub fn compress_file(input: &Path, output: &Path, codec: Codec, level: u32, threads: u32) -> Result<(), String> {
+    let mut reader = File::open(input)
+        .map_err(|e| format!("Failed to open file: {}", e))?;
+    let file = File::create(output)
+        .map_err(|e| format!("Failed to create file: {}", e))?;
+
+    let result = codec.encoder(BufWriter::new(file), level, threads).and_then(|mut encoder| {
+        io::copy(&mut reader, &mut encoder)
+            .and_then(|_| encoder.finish())
+            .and_then(|mut writer| writer.flush())
+            .map_err(|e| format!("Failed to compress {}: {}", input.display(), e))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+/// Decompress a single file into `output`, leaving nothing behind when the
+/// data turns out to be damaged or grows past the guard's limits
+pub fn decompress_file(input: &Path, output: &Path, codec: Codec, guard: &mut Guard) -> Result<(), String> {
+    let file = File::open(input)
+        .map_err(|e| format!("Failed to open file: {}", e))?;
+    let mut decoder = codec.decoder(BufReader::new(file))?;
+    let mut writer = BufWriter::new(GuardedWriter {
+        inner: File::create(output).map_err(|e| format!("Failed to create file: {}", e))?,
+        guard,
+    });
+
+    let result = io::copy(&mut decoder, &mut writer)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("Failed to decompress {}: {}", input.display(), e));
+    if result.is_err() {
+        drop(writer);
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+/// lz4 reports a stream cut short only when the decoder is finished
+struct Lz4Reader<R: Read>(Option<lz4::Decoder<R>>);
+
+impl<R: Read> Read for Lz4Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(decoder) = &mut self.0 else {
+            return Ok(0);
+        };
+        let read = decoder.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            // Reported as interrupted, which io::copy would retry past
+            if let Some(decoder) = self.0.take() {
+                decoder.finish().1
+                    .map_err(|_| io::Error::new(io::ErrorKind::UnexpectedEof, "lz4 stream ended early"))?;
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// A compressing writer, which has to be finished to write the end of the
+/// stream
+pub enum Encoder<W: Write> {
+    Gzip(GzEncoder<W>),
+    Bzip2(BzEncoder<W>),
+    Xz(XzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    Lz4(lz4::Encoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Bzip2(encoder) => encoder.finish(),
+            Encoder::Xz(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+            Encoder::Lz4(encoder) => {
+                let (writer, result) = encoder.finish();
+                result.map(|_| writer)
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Bzip2(encoder) => encoder.write(buf),
+            Encoder::Xz(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+            Encoder::Lz4(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Bzip2(encoder) => encoder.flush(),
+            Encoder::Xz(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+            Encoder::Lz4(encoder) => encoder.flush(),
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use crate::config::ExtractionLimits;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Ratios are only held against output above this size, which no ratio
+/// can make a danger to the disk
+const RATIO_FLOOR: u64 = 64 * 1024 * 1024;
+
+/// Free space is looked up again each time this much more is written
+const SPACE_CHECK_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// Keeps an extraction within the limits of the config: sizes the archive
+/// declares are checked before each entry is written, and the bytes really
+/// written as they are written, since a crafted archive may lie about its
+/// sizes. When a limit is hit, what was extracted can be removed again.
+pub struct Guard {
+    limits: ExtractionLimits,
+    output: PathBuf,
+    output_existed: bool,
+    archive_size: u64,
+    written: u64,
+    entries: u64,
+    next_space_check: u64,
+    created: Vec<PathBuf>,
+    tripped: bool,
+}
+
+impl Guard {
+    /// Made before `output` is created, so that it is known whether it
+    /// held anything from before
+    pub fn new(limits: &ExtractionLimits, archive: &Path, output: &Path) -> Self {
+        Self {
+            limits: limits.clone(),
+            output: output.to_path_buf(),
+            output_existed: output.exists(),
+            archive_size: fs::metadata(archive).map_or(0, |metadata| metadata.len()),
+            written: 0,
+            entries: 0,
+            next_space_check: 0,
+            created: Vec::new(),
+            tripped: false,
+        }
+    }
+
+    /// Check the entries an archive lists, as (path in the output, is a
+    /// folder, size), before extracting any of them
+    pub fn check_declared(&mut self, entries: &[(PathBuf, bool, u64)]) -> Result<(), String> {
+        let limits = &self.limits;
+        if limits.max_entries > 0 && entries.len() as u64 > limits.max_entries {
+            return Err(self.trip(format!(
+                "Archive has too many entries: {} (limit {})",
+                entries.len(),
+                limits.max_entries
+            )));
+        }
+        if let Some(depth) = entries.iter().map(|(relative, is_dir, _)| depth(relative, *is_dir)).max() {
+            self.check_depth(depth)?;
+        }
+        let total = entries.iter().fold(0u64, |total, (_, _, size)| total.saturating_add(*size));
+        self.check_size(total)?;
+        self.check_space(total)
+    }
+
+    /// Check an entry about to be extracted, with the size the archive
+    /// gives it
+    pub fn enter(&mut self, relative: &Path, is_dir: bool, size: u64) -> Result<(), String> {
+        self.entries += 1;
+        if self.limits.max_entries > 0 && self.entries > self.limits.max_entries {
+            return Err(self.trip(format!("Archive has too many entries (limit {})", self.limits.max_entries)));
+        }
+        self.check_depth(depth(relative, is_dir))?;
+        self.record_new(relative);
+
+        let total = self.written.saturating_add(size);
+        self.check_size(total)?;
+        if size > 0 {
+            self.check_space(size)?;
+        }
+        Ok(())
+    }
+
+    /// Count bytes written to the output
+    pub fn add(&mut self, bytes: u64) -> Result<(), String> {
+        self.written = self.written.saturating_add(bytes);
+        self.check_size(self.written)?;
+        if self.written >= self.next_space_check {
+            self.next_space_check = self.written + SPACE_CHECK_INTERVAL;
+            self.check_space(0)?;
+        }
+        Ok(())
+    }
+
+    /// Refuse an entry whose size is not known up front, for extraction
+    /// that cannot be stopped part way, while any size limit is set
+    pub fn reject_unknown_size(&mut self, name: &str) -> Result<(), String> {
+        let limits = &self.limits;
+        if limits.max_total_size > 0 || limits.max_ratio > 0 || limits.min_free_space > 0 {
+            return Err(self.trip(format!("Entry does not declare its size: {}", name)));
+        }
+        Ok(())
+    }
+
+    pub fn tripped(&self) -> bool {
+        self.tripped
+    }
+
+    /// Remove what was extracted: the whole output folder when it is new,
+    /// otherwise the files and folders the extraction created in it. What
+    /// was there before is left alone, and a folder is only removed once
+    /// empty.
+    pub fn clean_up(&self) {
+        if !self.output_existed {
+            let _ = fs::remove_dir_all(&self.output);
+            return;
+        }
+        for path in self.created.iter().rev() {
+            match fs::symlink_metadata(path) {
+                Ok(metadata) if metadata.is_dir() => {
+                    let _ = fs::remove_dir(path);
+                }
+                Ok(_) => {
+                    let _ = fs::remove_file(path);
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Note the entry's path and the folders leading to it that do not
+    /// exist yet, outermost first, so that they are known to be this
+    /// extraction's own
+    fn record_new(&mut self, relative: &Path) {
+        let target = self.output.join(relative);
+        let mut new: Vec<PathBuf> = target.ancestors()
+            .take_while(|path| *path != self.output && fs::symlink_metadata(path).is_err())
+            .map(Path::to_path_buf)
+            .collect();
+        new.reverse();
+        self.created.extend(new);
+    }
+
+    /// Folder nesting only: archives inside the archive are written out as
+    /// files and never opened, so they cannot nest an extraction
+    fn check_depth(&mut self, depth: usize) -> Result<(), String> {
+        let limit = self.limits.max_depth as usize;
+        if limit > 0 && depth > limit {
+            return Err(self.trip(format!("Folders are nested too deeply: {} levels (limit {})", depth, limit)));
+        }
+        Ok(())
+    }
+
+    fn check_size(&mut self, total: u64) -> Result<(), String> {
+        let limits = &self.limits;
+        if limits.max_total_size > 0 && total > limits.max_total_size {
+            return Err(self.trip(format!(
+                "Archive expands past the size limit of {}",
+                format_size(limits.max_total_size)
+            )));
+        }
+        if limits.max_ratio > 0 && total > RATIO_FLOOR && total / self.archive_size.max(1) > limits.max_ratio {
+            return Err(self.trip(format!(
+                "Archive expands {}:1, more than the limit of {}:1",
+                total / self.archive_size.max(1),
+                limits.max_ratio
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether `needed` more bytes fit on the disk leaving the free space
+    /// asked for. A disk that cannot be queried is not checked.
+    fn check_space(&mut self, needed: u64) -> Result<(), String> {
+        if self.limits.min_free_space == 0 {
+            return Ok(());
+        }
+        let existing = self.output.ancestors().find(|folder| folder.exists());
+        let Some(available) = existing.and_then(|folder| fs2::available_space(folder).ok()) else {
+            return Ok(());
+        };
+        if available.saturating_sub(needed) < self.limits.min_free_space {
+            return Err(self.trip(format!(
+                "Not enough disk space: {} needed, {} free, {} to be kept free",
+                format_size(needed),
+                format_size(available),
+                format_size(self.limits.min_free_space)
+            )));
+        }
+        Ok(())
+    }
+
+    fn trip(&mut self, message: String) -> String {
+        self.tripped = true;
+        message
+    }
+}
+
+/// A writer counting what goes through it against the guard's limits
+pub struct GuardedWriter<'a, W: Write> {
+    pub inner: W,
+    pub guard: &'a mut Guard,
+}
+
+impl<W: Write> Write for GuardedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.guard.add(written as u64).map_err(io::Error::other)?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Folders an entry lies in, counting a folder entry itself
+fn depth(relative: &Path, is_dir: bool) -> usize {
+    let components = relative.components().count();
+    if is_dir {
+        components
+    } else {
+        components.saturating_sub(1)
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} bytes", bytes),
+        1024..=1_048_575 => format!("{} KB", bytes.div_ceil(1024)),
+        _ => format!("{} MB", bytes.div_ceil(1024 * 1024)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard_with(limits: ExtractionLimits) -> Guard {
+        // A missing archive counts as one byte, which makes every ratio large
+        Guard::new(&limits, Path::new("missing.zip"), Path::new("missing_output"))
+    }
+
+    fn no_limits() -> ExtractionLimits {
+        ExtractionLimits {
+            max_total_size: 0,
+            max_ratio: 0,
+            max_entries: 0,
+            max_depth: 0,
+            min_free_space: 0,
+        }
+    }
+
+    #[test]
+    fn ratio_is_only_held_above_the_floor() {
+        let mut guard = guard_with(ExtractionLimits { max_ratio: 10, ..no_limits() });
+        assert!(guard.add(RATIO_FLOOR).is_ok());
+        assert!(!guard.tripped());
+        assert!(guard.add(1).is_err());
+        assert!(guard.tripped());
+    }
+
+    #[test]
+    fn depth_counts_folders_only() {
+        let mut guard = guard_with(ExtractionLimits { max_depth: 2, ..no_limits() });
+        assert!(guard.enter(Path::new("a/b/file.txt"), false, 0).is_ok());
+        assert!(guard.enter(Path::new("a/b"), true, 0).is_ok());
+        assert!(guard.enter(Path::new("a/b/c"), true, 0).is_err());
+
+        let mut guard = guard_with(ExtractionLimits { max_depth: 2, ..no_limits() });
+        let declared = [(PathBuf::from("a/b/c/file.txt"), false, 0)];
+        assert!(guard.check_declared(&declared).is_err());
+    }
+
+    #[test]
+    fn entry_count_is_limited() {
+        let mut guard = guard_with(ExtractionLimits { max_entries: 2, ..no_limits() });
+        assert!(guard.enter(Path::new("a.txt"), false, 0).is_ok());
+        assert!(guard.enter(Path::new("b.txt"), false, 0).is_ok());
+        assert!(guard.enter(Path::new("c.txt"), false, 0).is_err());
+
+        let mut guard = guard_with(ExtractionLimits { max_entries: 2, ..no_limits() });
+        let declared: Vec<_> = ["a", "b", "c"].iter().map(|name| (PathBuf::from(name), false, 0)).collect();
+        assert!(guard.check_declared(&declared).is_err());
+    }
+
+    #[test]
+    fn declared_sizes_do_not_overflow() {
+        let mut guard = guard_with(ExtractionLimits { max_total_size: 1024, ..no_limits() });
+        let declared = [(PathBuf::from("a"), false, u64::MAX), (PathBuf::from("b"), false, 1)];
+        assert!(guard.check_declared(&declared).is_err());
+    }
+}
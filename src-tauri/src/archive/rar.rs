@@ -0,0 +1,313 @@
+use super::guard::Guard;
+use super::{Selection, TestRun};
+use crate::commands::ArchiveEntry;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use unrar::error::{Code, UnrarError};
+use unrar::{Archive, VolumeInfo};
+
+/// Volumes are looked for up to this number when reporting missing ones
+/// (old-style names run out at `.z99`)
+const MAX_VOLUMES: usize = 900;
+
+/// The size the library reports for an entry whose header leaves it
+/// unknown, which it then extracts to the end of the data
+const UNKNOWN_SIZE: u64 = 0x7fff_ffff_7fff_ffff;
+
+/// Whether an extension belongs to a RAR archive or one of its first
+/// old-style volumes (`.r00` to `.r99`). Volumes past those (`.s00`, ...)
+/// are found from the first one, so other formats using such names are
+/// not taken for RAR.
+pub fn is_rar_extension(extension: &str) -> bool {
+    let extension = extension.to_ascii_lowercase();
+    extension == "rar"
+        || (extension.len() == 3
+            && extension.starts_with('r')
+            && extension[1..].chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Extract the chosen entries of a RAR4 or RAR5 archive, decrypting with
+/// `password`. A volume of a multi-volume set may be given; extraction
+/// starts at the first volume.
+pub fn extract(
+    path: &str,
+    output: &Path,
+    password: Option<&str>,
+    selection: &mut Selection,
+    guard: &mut Guard,
+) -> Result<(), String> {
+    let (volumes, first) = locate(path)?;
+    let archive = match password {
+        Some(password) => Archive::with_password(&first, password),
+        None => Archive::new(&first),
+    };
+    let fail = |error: UnrarError, encrypted: bool| {
+        describe_error(error, path, password.is_some() && encrypted, volumes.as_ref())
+    };
+
+    let mut archive = archive.open_for_processing()
+        .map_err(|e| fail(e, true))?;
+    let encrypted_headers = archive.has_encrypted_headers();
+    // Only a multi-volume set can fail for want of a volume once open
+    let volumes = volumes.filter(|_| archive.volume_info() != VolumeInfo::None);
+    let fail = |error: UnrarError, encrypted: bool| {
+        describe_error(error, path, password.is_some() && encrypted, volumes.as_ref())
+    };
+
+    while let Some(header) = archive.read_header().map_err(|e| fail(e, encrypted_headers))? {
+        let entry = header.entry();
+        let encrypted = entry.is_encrypted();
+        let is_directory = entry.is_directory();
+        let relative = selection.target(&entry.filename.to_string_lossy(), is_directory);
+        if let Some(relative) = &relative {
+            // The library writes the file itself and stops at the size the
+            // header gives, so that size is what the guard has to go by
+            if !is_directory && entry.unpacked_size == UNKNOWN_SIZE {
+                guard.reject_unknown_size(&entry.filename.to_string_lossy())?;
+            }
+            guard.enter(relative, is_directory, entry.unpacked_size)?;
+        }
+        let target = relative.map(|relative| output.join(relative));
+
+        if let Some(target) = &target {
+            super::stays_inside(output, target)?;
+        }
+
+        archive = match target {
+            Some(target) if is_directory => {
+                fs::create_dir_all(&target)
+                    .map_err(|e| format!("Failed to create directory: {}", e))?;
+                header.skip().map_err(|e| fail(e, encrypted))?
+            }
+            Some(target) => {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)
+                        .map_err(|e| format!("Failed to create parent directory: {}", e))?;
+                }
+                let next = header.extract_to(&target).map_err(|e| fail(e, encrypted))?;
+                // Counted once done, which the size checked on entering bounds
+                guard.add(fs::metadata(&target).map_or(0, |metadata| metadata.len()))?;
+                next
+            }
+            None => header.skip().map_err(|e| fail(e, encrypted))?,
+        };
+    }
+
+    Ok(())
+}
+
+/// Decompress every file of a RAR archive, which checks its CRC. The
+/// library cannot go on past a file that fails, so the test stops there.
+pub fn test(path: &str, password: Option<&str>) -> Result<TestRun, String> {
+    let (volumes, first) = locate(path)?;
+    let archive = match password {
+        Some(password) => Archive::with_password(&first, password),
+        None => Archive::new(&first),
+    };
+    let fail = |error: UnrarError, encrypted: bool| {
+        describe_error(error, path, password.is_some() && encrypted, volumes.as_ref())
+    };
+
+    let mut archive = archive.open_for_processing()
+        .map_err(|e| fail(e, true))?;
+    let encrypted_headers = archive.has_encrypted_headers();
+    let volumes = volumes.filter(|_| archive.volume_info() != VolumeInfo::None);
+    let fail = |error: UnrarError, encrypted: bool| {
+        describe_error(error, path, password.is_some() && encrypted, volumes.as_ref())
+    };
+
+    let mut run = TestRun::default();
+    while let Some(header) = archive.read_header().map_err(|e| fail(e, encrypted_headers))? {
+        let entry = header.entry();
+        if entry.is_directory() {
+            archive = header.skip().map_err(|e| fail(e, encrypted_headers))?;
+            continue;
+        }
+        let name = entry.filename.to_string_lossy().to_string();
+        let (size, encrypted) = (entry.unpacked_size, entry.is_encrypted());
+
+        match header.test() {
+            Ok(next) => {
+                run.record(name, size, encrypted, Ok(()));
+                archive = next;
+            }
+            Err(e) if matches!(e.code, Code::MissingPassword | Code::BadPassword) => {
+                return Err(fail(e, encrypted));
+            }
+            Err(e) => {
+                run.record(name.clone(), size, encrypted, Err(fail(e, encrypted)));
+                run.stopped = Some(format!("Testing stopped at {}", name));
+                break;
+            }
+        }
+    }
+    Ok(run)
+}
+
+/// Entries of a RAR archive, read from its headers. RAR gives no
+/// compressed sizes through its listing, and a file split over volumes is
+/// listed once.
+pub fn list(path: &str, password: Option<&str>) -> Result<Vec<ArchiveEntry>, String> {
+    let (volumes, first) = locate(path)?;
+    let archive = match password {
+        Some(password) => Archive::with_password(&first, password),
+        None => Archive::new(&first),
+    };
+    let fail = |error: UnrarError| describe_error(error, path, password.is_some(), volumes.as_ref());
+
+    let mut entries = Vec::new();
+    for header in archive.open_for_listing().map_err(fail)? {
+        let header = header.map_err(fail)?;
+        if header.is_split_before() {
+            continue;
+        }
+        entries.push(ArchiveEntry {
+            path: header.filename.to_string_lossy().to_string(),
+            is_dir: header.is_directory(),
+            size: header.unpacked_size,
+            compressed_size: None,
+            modified: super::dos_timestamp(header.file_time),
+            encrypted: header.is_encrypted(),
+            crc: (!header.is_directory()).then_some(header.file_crc),
+            method: Some(method_name(header.method).to_string()),
+        });
+    }
+    Ok(entries)
+}
+
+/// The archive's first volume, and the set it belongs to when its name is
+/// that of a volume
+fn locate(path: &str) -> Result<(Option<VolumeSet>, PathBuf), String> {
+    if !Path::new(path).exists() {
+        return Err(format!("Failed to open RAR archive: file not found: {}", path));
+    }
+    let volumes = VolumeSet::detect(Path::new(path));
+    let first = volumes.as_ref().map_or_else(|| PathBuf::from(path), |volumes| volumes.volume(0));
+    Ok((volumes, first))
+}
+
+/// Compression methods by the names WinRAR gives them
+fn method_name(method: u32) -> &'static str {
+    match method {
+        0x30 => "Store",
+        0x31 => "Fastest",
+        0x32 => "Fast",
+        0x33 => "Normal",
+        0x34 => "Good",
+        0x35 => "Best",
+        _ => "Unknown",
+    }
+}
+
+/// `encrypted` is set when the failing header or entry was encrypted with
+/// the password given, so that damage is most likely a wrong key
+fn describe_error(error: UnrarError, path: &str, encrypted: bool, volumes: Option<&VolumeSet>) -> String {
+    match error.code {
+        Code::MissingPassword => super::password_required(path),
+        Code::BadPassword => super::wrong_password(path),
+        // RAR4 keeps no password check value, so a wrong key shows up as bad data
+        Code::BadData if encrypted => super::wrong_password(path),
+        Code::EOpen => match volumes.map(VolumeSet::missing) {
+            Some(missing) if !missing.is_empty() => {
+                let names: Vec<String> = missing.iter()
+                    .filter_map(|volume| volume.file_name())
+                    .map(|name| name.to_string_lossy().to_string())
+                    .collect();
+                format!("Missing archive volume: {}", names.join(", "))
+            }
+            _ => format!("Failed to open RAR archive: {}", error),
+        },
+        _ => format!("Failed to read RAR archive: {}", error),
+    }
+}
+
+/// The volumes of a multi-volume set, as named on disk
+struct VolumeSet {
+    dir: PathBuf,
+    naming: Naming,
+}
+
+enum Naming {
+    /// `name.part1.rar`, `name.part2.rar`, ...
+    Numbered {
+        prefix: String,
+        width: usize,
+        suffix: String,
+    },
+    /// `name.rar`, `name.r00`, `name.r01`, ... `name.r99`, `name.s00`, ...
+    Legacy {
+        stem: String,
+        uppercase: bool,
+    },
+}
+
+impl VolumeSet {
+    fn detect(path: &Path) -> Option<Self> {
+        let dir = path.parent()?.to_path_buf();
+        let name = path.file_name()?.to_str()?;
+
+        let numbered = Regex::new(r"(?i)^(.*\.part)(\d+)(\.rar)$").ok()?;
+        let naming = match numbered.captures(name) {
+            Some(captures) => Naming::Numbered {
+                prefix: captures[1].to_string(),
+                width: captures[2].len(),
+                suffix: captures[3].to_string(),
+            },
+            None => {
+                let (stem, extension) = name.rsplit_once('.')?;
+                if !is_rar_extension(extension) {
+                    return None;
+                }
+                Naming::Legacy {
+                    stem: stem.to_string(),
+                    uppercase: extension.starts_with(|c: char| c.is_ascii_uppercase()),
+                }
+            }
+        };
+
+        Some(Self { dir, naming })
+    }
+
+    /// Path of the volume at `index`, counting from zero
+    fn volume(&self, index: usize) -> PathBuf {
+        let name = match &self.naming {
+            Naming::Numbered { prefix, width, suffix } => {
+                format!("{}{:0width$}{}", prefix, index + 1, suffix, width = *width)
+            }
+            Naming::Legacy { stem, uppercase } => {
+                let extension = match index {
+                    0 => "rar".to_string(),
+                    _ => {
+                        let number = index - 1;
+                        format!("{}{:02}", (b'r' + (number / 100) as u8) as char, number % 100)
+                    }
+                };
+                match uppercase {
+                    true => format!("{}.{}", stem, extension.to_ascii_uppercase()),
+                    false => format!("{}.{}", stem, extension),
+                }
+            }
+        };
+        self.dir.join(name)
+    }
+
+    /// Gaps before the last volume present or, when there are none, the
+    /// volume that should follow it
+    fn missing(&self) -> Vec<PathBuf> {
+        let present: Vec<bool> = (0..MAX_VOLUMES).map(|index| self.volume(index).exists()).collect();
+        let Some(last) = present.iter().rposition(|&exists| exists) else {
+            return vec![self.volume(0)];
+        };
+
+        let gaps: Vec<PathBuf> = (0..last)
+            .filter(|&index| !present[index])
+            .map(|index| self.volume(index))
+            .collect();
+        if gaps.is_empty() {
+            vec![self.volume(last + 1)]
+        } else {
+            gaps
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use super::guard::Guard;
+use super::{EntryError, Selection, Source, TestRun};
+use crate::commands::{ArchiveEntry, ArchiveOptions};
+use sevenz_rust::lzma::LZMA2Options;
+use sevenz_rust::{
+    AesEncoderOptions, Archive, BlockDecoder, Error, Password, SeqReader, SevenZArchiveEntry,
+    SevenZMethod, SevenZMethodConfiguration, SevenZReader, SevenZWriter, SourceReader,
+};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// LZMA2 preset used when no level is chosen, as in xz
+const DEFAULT_LEVEL: u32 = 6;
+
+/// Extract the chosen entries of a 7z archive, decrypting AES-256 data and
+/// headers with `password`
+pub fn extract(
+    path: &str,
+    output: &Path,
+    password: Option<&str>,
+    selection: &mut Selection,
+    guard: &mut Guard,
+) -> Result<(), String> {
+    let mut reader = SevenZReader::open(path, password.map_or_else(Password::empty, Password::from))
+        .map_err(|e| describe_error(e, path, password.is_some()))?;
+
+    // Failures writing the output are kept apart from decoding errors,
+    // which the reader reports as a possibly wrong password
+    let mut write_error = None;
+    let result = reader.for_each_entries(|entry, data| {
+        let Some(relative) = selection.target(entry.name(), entry.is_directory()) else {
+            // Entries of a solid block are decoded in sequence, so the data
+            // of a skipped entry still has to be read through
+            io::copy(data, &mut io::sink())?;
+            return Ok(true);
+        };
+        if let Err(message) = guard.enter(&relative, entry.is_directory(), entry.size()) {
+            write_error = Some(message);
+            return Ok(false);
+        }
+        let target = output.join(relative);
+
+        match super::write_entry(output, &target, entry.is_directory(), data, guard) {
+            Ok(()) => Ok(true),
+            Err(EntryError::Read(e)) => Err(Error::io(e)),
+            Err(EntryError::Write(message)) => {
+                write_error = Some(message);
+                Ok(false)
+            }
+        }
+    });
+
+    if let Some(message) = write_error {
+        return Err(message);
+    }
+    result.map_err(|e| describe_error(e, path, password.is_some()))
+}
+
+/// Entries of a 7z archive. Files of a solid block share its compressed
+/// data, so a compressed size is only known for files stored alone.
+pub fn list(path: &str, password: Option<&str>) -> Result<Vec<ArchiveEntry>, String> {
+    let reader = SevenZReader::open(path, password.map_or_else(Password::empty, Password::from))
+        .map_err(|e| describe_error(e, path, password.is_some()))?;
+    let archive = reader.archive();
+
+    let entries = archive.files.iter().enumerate().map(|(i, entry)| {
+        let folder_index = archive.stream_map.file_folder_index[i];
+        let folder = folder_index.map(|index| (index, &archive.folders[index]));
+        let encrypted = folder.is_some_and(|(_, folder)| {
+            folder.coders.iter().any(|coder| coder.decompression_method_id() == SevenZMethod::ID_AES256SHA256)
+        });
+        let method = folder.map(|(_, folder)| {
+            folder.coders.iter()
+                .map(|coder| coder.decompression_method_id())
+                .filter(|&id| id != SevenZMethod::ID_AES256SHA256)
+                .map(|id| SevenZMethod::by_id(id).map_or("Unknown", |method| method.name()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        let compressed_size = folder
+            .filter(|(_, folder)| folder.num_unpack_sub_streams == 1)
+            .map(|(index, folder)| {
+                let first = archive.stream_map.folder_first_pack_stream_index[index];
+                archive.pack_sizes[first..first + folder.packed_streams.len()].iter().sum()
+            });
+
+        ArchiveEntry {
+            path: entry.name().to_string(),
+            is_dir: entry.is_directory(),
+            size: entry.size(),
+            compressed_size,
+            modified: entry.has_last_modified_date
+                .then(|| super::system_timestamp(entry.last_modified_date().into())),
+            encrypted,
+            crc: entry.has_crc.then_some(entry.crc as u32),
+            method,
+        }
+    });
+    Ok(entries.collect())
+}
+
+/// Decompress every file of a 7z archive, which checks its CRC-32. Files
+/// of a solid block come out of one stream, so those after a damaged file
+/// in the block cannot be reached.
+pub fn test(path: &str, password: Option<&str>) -> Result<TestRun, String> {
+    let password = password.map_or_else(Password::empty, Password::from);
+    let mut file = File::open(path)
+        .map_err(|e| format!("Failed to open 7z file: {}", e))?;
+    let length = file.metadata()
+        .map_err(|e| format!("Failed to open 7z file: {}", e))?
+        .len();
+    let archive = Archive::read(&mut file, length, password.as_slice())
+        .map_err(|e| describe_error(e, path, !password.is_empty()))?;
+
+    let mut run = TestRun::default();
+    for (folder_index, folder) in archive.folders.iter().enumerate() {
+        let encrypted = folder.coders.iter()
+            .any(|coder| coder.decompression_method_id() == SevenZMethod::ID_AES256SHA256);
+        let start = archive.stream_map.folder_first_file_index[folder_index];
+        let files = &archive.files[start..start + folder.num_unpack_sub_streams];
+
+        let mut reached = 0;
+        let decoder = BlockDecoder::new(folder_index, &archive, password.as_slice(), &mut file);
+        let result = decoder.for_each_entries(&mut |entry, data| {
+            reached += 1;
+            if entry.is_directory() {
+                return Ok(true);
+            }
+            let result = io::copy(data, &mut io::sink())
+                .map(|_| ())
+                .map_err(|e| format!("Corrupted data: {}", e));
+            let passed = result.is_ok();
+            run.record(entry.name().to_string(), entry.size(), encrypted, result);
+            Ok(passed)
+        });
+
+        let unreached = match result {
+            Err(Error::PasswordRequired) => return Err(super::password_required(path)),
+            Err(e) => format!("Failed to read 7z archive: {}", e),
+            Ok(_) => "Not tested: an earlier file in the same solid block is damaged".to_string(),
+        };
+        for entry in files.iter().skip(reached).filter(|entry| !entry.is_directory()) {
+            run.record(entry.name().to_string(), entry.size(), encrypted, Err(unreached.clone()));
+        }
+    }
+
+    // Empty files have no data to check
+    let empty = archive.files.iter().zip(&archive.stream_map.file_folder_index)
+        .filter(|(entry, folder)| folder.is_none() && !entry.is_directory());
+    for (entry, _) in empty {
+        run.record(entry.name().to_string(), 0, false, Ok(()));
+    }
+    Ok(run)
+}
+
+fn describe_error(error: Error, path: &str, has_password: bool) -> String {
+    match error {
+        Error::PasswordRequired => super::password_required(path),
+        Error::MaybeBadPassword(_) => super::wrong_password(path),
+        // Headers decrypted with the wrong key come out as garbage
+        Error::ChecksumVerificationFailed
+        | Error::BadTerminatedheader(_)
+        | Error::BadTerminatedStreamsInfo(_)
+        | Error::BadTerminatedUnpackInfo
+        | Error::BadTerminatedPackInfo(_)
+        | Error::BadTerminatedSubStreamsInfo
+            if has_password => super::wrong_password(path),
+        Error::FileOpen(e, _) => format!("Failed to open 7z file: {}", e),
+        e => format!("Failed to read 7z archive: {}", e),
+    }
+}
+
+/// Create a 7z archive compressed with LZMA2 at the chosen preset level.
+/// Files go into one solid block unless `solid` is turned off. With a
+/// password, contents are encrypted with AES-256, and file names too when
+/// `encrypt_names` is set.
+pub fn create(sources: &[Source], output: &Path, password: Option<&str>, options: &ArchiveOptions) -> Result<(), String> {
+    let level = options.level.unwrap_or(DEFAULT_LEVEL);
+    if level > 9 {
+        return Err("Compression level must be between 0 and 9".to_string());
+    }
+
+    let mut writer = SevenZWriter::create(output)
+        .map_err(|e| format!("Failed to create 7z file: {}", e))?;
+    let mut methods: Vec<SevenZMethodConfiguration> = Vec::new();
+    if let Some(password) = password {
+        methods.push(AesEncoderOptions::new(Password::from(password)).into());
+    }
+    methods.push(LZMA2Options::with_preset(level).into());
+    writer.set_content_methods(methods);
+    writer.set_encrypt_header(password.is_some() && options.encrypt_names);
+
+    // Empty files and folders have no data and are stored as entries alone
+    let mut streams = Vec::new();
+    for source in sources {
+        let entry = SevenZArchiveEntry::from_path(&source.path, source.name.clone());
+        let has_data = !source.is_dir && fs::metadata(&source.path)
+            .map_err(|e| format!("Failed to read file info: {}", e))?
+            .len() > 0;
+
+        if !has_data {
+            writer.push_archive_entry(entry, None::<File>)
+                .map_err(|e| format!("Failed to write 7z entry: {}", e))?;
+        } else if options.solid.unwrap_or(true) {
+            streams.push((entry, source.path.clone()));
+        } else {
+            let file = File::open(&source.path)
+                .map_err(|e| format!("Failed to open file: {}", e))?;
+            writer.push_archive_entry(entry, Some(file))
+                .map_err(|e| format!("Failed to write 7z entry: {}", e))?;
+        }
+    }
+
+    if !streams.is_empty() {
+        let (entries, paths): (Vec<_>, Vec<_>) = streams.into_iter().unzip();
+        let readers = paths.into_iter()
+            .map(|path| SourceReader::new(LazyFile { path, file: None }))
+            .collect();
+        writer.push_archive_entries(entries, SeqReader::new(readers))
+            .map_err(|e| format!("Failed to write 7z entry: {}", e))?;
+    }
+
+    writer.finish()
+        .map_err(|e| format!("Failed to finish 7z: {}", e))?;
+
+    // The writer stores the header in the clear when compressing it gains
+    // nothing, which happens with only a few short names
+    if password.is_some() && options.encrypt_names && SevenZReader::open(output, Password::empty()).is_ok() {
+        let _ = fs::remove_file(output);
+        return Err("File names cannot be encrypted in an archive this small; add more files or turn off name encryption".to_string());
+    }
+    Ok(())
+}
+
+/// A file of a solid block, opened only when its turn comes so that large
+/// folders do not run out of file handles
+struct LazyFile {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl Read for LazyFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(File::open(&self.path)?),
+        };
+        file.read(buf)
+    }
+}
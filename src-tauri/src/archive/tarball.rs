@@ -0,0 +1,369 @@
+use super::codec::{self, Codec};
+use super::guard::Guard;
+use super::{Selection, Source, TestRun};
+use crate::commands::ArchiveEntry;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use tar::{Archive, Builder, EntryType};
+
+/// Tarball suffixes and the compression each stands for
+const SUFFIXES: [(&str, Option<Codec>); 13] = [
+    (".tar", None),
+    (".tar.gz", Some(Codec::Gzip)),
+    (".tgz", Some(Codec::Gzip)),
+    (".tar.bz2", Some(Codec::Bzip2)),
+    (".tbz2", Some(Codec::Bzip2)),
+    (".tbz", Some(Codec::Bzip2)),
+    (".tar.xz", Some(Codec::Xz)),
+    (".txz", Some(Codec::Xz)),
+    (".tar.zst", Some(Codec::Zstd)),
+    (".tar.zstd", Some(Codec::Zstd)),
+    (".tzst", Some(Codec::Zstd)),
+    (".tzs", Some(Codec::Zstd)),
+    (".tar.lz4", Some(Codec::Lz4)),
+];
+
+/// A tarball file name: the name without its suffix, and the compression
+/// the suffix stands for
+pub struct Tarball {
+    pub stem: String,
+    pub codec: Option<Codec>,
+}
+
+impl Tarball {
+    /// Recognizes double extensions like `.tar.gz` as well as `.tgz`
+    pub fn detect(name: &str) -> Option<Self> {
+        SUFFIXES.iter().find_map(|&(suffix, codec)| {
+            let split = name.len().checked_sub(suffix.len())?;
+            let tail = name.get(split..)?;
+            tail.eq_ignore_ascii_case(suffix).then(|| Self {
+                stem: name[..split].to_string(),
+                codec,
+            })
+        })
+    }
+}
+
+/// Extract the chosen entries of a tarball, keeping modification times and
+/// permission bits. Links are made only when they point inside the
+/// extraction folder, and not at all when flattening; device files are left
+/// out.
+pub fn extract(
+    path: &str,
+    output: &Path,
+    codec: Option<Codec>,
+    selection: &mut Selection,
+    guard: &mut Guard,
+) -> Result<(), String> {
+    let mut archive = open(path, codec)?;
+    archive.set_preserve_mtime(true);
+
+    let mut links = Vec::new();
+    let entries = archive.entries()
+        .map_err(|e| format!("Failed to read tar archive: {}", e))?;
+    for entry in entries {
+        let mut entry = entry
+            .map_err(|e| format!("Failed to read tar entry: {}", e))?;
+        let name = entry.path()
+            .map_err(|e| format!("Failed to read tar entry: {}", e))?
+            .to_string_lossy()
+            .to_string();
+
+        let entry_type = entry.header().entry_type();
+        let is_link = match entry_type {
+            EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse | EntryType::Directory => false,
+            EntryType::Symlink | EntryType::Link if !selection.flatten => true,
+            _ => continue,
+        };
+        let Some(relative) = selection.target(&name, entry_type == EntryType::Directory) else {
+            continue;
+        };
+
+        if is_link {
+            let target = entry.link_name()
+                .map_err(|e| format!("Failed to read tar entry: {}", e))?;
+            let Some(target) = target else {
+                continue;
+            };
+            if !link_is_enclosed(&relative, &target, entry_type == EntryType::Symlink) {
+                continue;
+            }
+            // A hard link needs its target extracted first, and must not
+            // reach it through a link leading out
+            if entry_type == EntryType::Link
+                && !(super::enclosed_path(&target.to_string_lossy()).is_some_and(|target| selection.selects(&target))
+                    && super::stays_inside(output, &output.join(&target)).is_ok())
+            {
+                continue;
+            }
+        }
+
+        // Tar sizes are exact, so the limits hold before anything is written
+        guard.enter(&relative, entry_type == EntryType::Directory, entry.size())?;
+        if is_link {
+            remove_earlier(output, &relative);
+        }
+
+        // The tar crate refuses on its own to write through a link leading
+        // out of the folder. Flattened files are written under their new
+        // name, straight into it.
+        let result = match selection.flatten {
+            true => {
+                super::stays_inside(output, &output.join(&relative))?;
+                entry.unpack(output.join(&relative)).map(|_| ())
+            }
+            false => entry.unpack_in(output).map(|_| ()),
+        };
+        match result {
+            Ok(()) => {}
+            // Windows only lets symbolic links be made with developer mode on
+            Err(_) if cfg!(windows) && entry_type == EntryType::Symlink => {}
+            Err(e) => return Err(format!("Failed to extract {}: {}", name, e)),
+        }
+        guard.add(entry.size())?;
+        if entry_type == EntryType::Symlink {
+            links.push(output.join(&relative));
+        }
+    }
+
+    remove_escaping_links(output, &links);
+    Ok(())
+}
+
+/// Read a tarball through to the end. Tar keeps no checksum of file data,
+/// so only the compression, apart from lz4, catches damage; it cannot be
+/// read past a damaged part.
+pub fn test(path: &str, codec: Option<Codec>) -> Result<TestRun, String> {
+    let mut archive = open(path, codec)?;
+    let mut run = TestRun::default();
+
+    let entries = archive.entries()
+        .map_err(|e| format!("Failed to read tar archive: {}", e))?;
+    for entry in entries {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                run.stopped = Some(format!("Failed to read tar entry: {}", e));
+                return Ok(run);
+            }
+        };
+        if !matches!(
+            entry.header().entry_type(),
+            EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse
+        ) {
+            continue;
+        }
+
+        let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        let size = entry.size();
+        if let Err(e) = io::copy(&mut entry, &mut io::sink()) {
+            run.record(name.clone(), size, false, Err(format!("Corrupted data: {}", e)));
+            run.stopped = Some(format!("Testing stopped at {}", name));
+            return Ok(run);
+        }
+        run.record(name, size, false, Ok(()));
+    }
+
+    // The stream checksum comes after the end of the tar data
+    if let Err(e) = io::copy(&mut archive.into_inner(), &mut io::sink()) {
+        run.stopped = Some(format!("Corrupted {} stream: {}", codec.map_or("tar", Codec::name), e));
+    }
+    Ok(run)
+}
+
+/// Entries of a tarball. It has no index, so the whole stream is read
+/// through; compression applies to the stream, not to each entry.
+pub fn list(path: &str, codec: Option<Codec>) -> Result<Vec<ArchiveEntry>, String> {
+    let mut archive = open(path, codec)?;
+    let entries = archive.entries()
+        .map_err(|e| format!("Failed to read tar archive: {}", e))?;
+
+    let mut listed = Vec::new();
+    for entry in entries {
+        let entry = entry
+            .map_err(|e| format!("Failed to read tar entry: {}", e))?;
+        let header = entry.header();
+        if header.entry_type().is_pax_global_extensions() {
+            continue;
+        }
+        let is_dir = header.entry_type() == EntryType::Directory;
+        listed.push(ArchiveEntry {
+            path: entry.path()
+                .map_err(|e| format!("Failed to read tar entry: {}", e))?
+                .to_string_lossy()
+                .to_string(),
+            is_dir,
+            size: if is_dir { 0 } else { entry.size() },
+            modified: header.mtime().ok()
+                .map(|seconds| super::system_timestamp(UNIX_EPOCH + Duration::from_secs(seconds))),
+            ..Default::default()
+        });
+    }
+    Ok(listed)
+}
+
+fn open(path: &str, codec: Option<Codec>) -> Result<Archive<Box<dyn Read>>, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Failed to open tar file: {}", e))?;
+    let reader = BufReader::new(file);
+    let reader: Box<dyn Read> = match codec {
+        Some(codec) => codec.decoder(reader)?,
+        None => Box::new(reader),
+    };
+    Ok(Archive::new(reader))
+}
+
+/// Links cannot be made over a file, as left by extracting the same archive
+/// before. Only files whose folder is really inside the output are removed.
+fn remove_earlier(output: &Path, relative: &Path) {
+    let target = output.join(relative);
+    let (Some(parent), Some(file_name)) = (target.parent(), target.file_name()) else {
+        return;
+    };
+    let (Ok(parent), Ok(output)) = (parent.canonicalize(), output.canonicalize()) else {
+        return;
+    };
+    let target = parent.join(file_name);
+    if parent.starts_with(&output) && fs::symlink_metadata(&target).is_ok_and(|metadata| !metadata.is_dir()) {
+        let _ = fs::remove_file(&target);
+    }
+}
+
+/// Remove symbolic links that lead out of the folder once resolved on disk.
+/// Each target is checked by name as it is extracted, but links can point
+/// through one another, so only the finished tree shows where they lead.
+/// A link that leads nowhere is removed too, since a later link or
+/// extraction could make it lead out.
+fn remove_escaping_links(output: &Path, links: &[PathBuf]) {
+    let Ok(output) = output.canonicalize() else {
+        return;
+    };
+    for link in links {
+        let is_link = fs::symlink_metadata(link).is_ok_and(|metadata| metadata.file_type().is_symlink());
+        let enclosed = link.canonicalize().is_ok_and(|resolved| resolved.starts_with(&output));
+        if is_link && !enclosed {
+            let _ = fs::remove_file(link);
+        }
+    }
+}
+
+/// Whether a link stays inside the extraction folder: symbolic link targets
+/// are taken from the link's own folder, hard link targets from the root
+fn link_is_enclosed(relative: &Path, target: &Path, symbolic: bool) -> bool {
+    let target = target.to_string_lossy().replace('\\', "/");
+    if target.starts_with('/') {
+        return false;
+    }
+
+    let base = match relative.parent() {
+        Some(parent) if symbolic => parent.to_string_lossy().replace('\\', "/"),
+        _ => String::new(),
+    };
+    let joined = if base.is_empty() {
+        target
+    } else {
+        format!("{}/{}", base, target)
+    };
+    super::enclosed_path(&joined).is_some()
+}
+
+/// Create a tarball, compressed at `level` unless `codec` is None. Entries
+/// carry the permissions and modification times of their sources, and
+/// symbolic links pointing inside what is archived stay links.
+pub fn create(sources: &[Source], output: &Path, codec: Option<Codec>, level: Option<u32>) -> Result<(), String> {
+    let file = File::create(output)
+        .map_err(|e| format!("Failed to create tar file: {}", e))?;
+    let writer = BufWriter::new(file);
+
+    match codec {
+        Some(codec) => {
+            let encoder = codec.encoder(writer, codec.level(level)?, codec::default_threads())?;
+            let encoder = append_sources(Builder::new(encoder), sources)?;
+            encoder.finish()
+                .map_err(|e| format!("Failed to finish {}: {}", codec.name(), e))?
+                .flush()
+                .map_err(|e| format!("Failed to finish tar: {}", e))
+        }
+        None => append_sources(Builder::new(writer), sources)?
+            .flush()
+            .map_err(|e| format!("Failed to finish tar: {}", e)),
+    }
+}
+
+fn append_sources<W: Write>(mut builder: Builder<W>, sources: &[Source]) -> Result<W, String> {
+    for source in sources {
+        builder.follow_symlinks(!is_enclosed_link(source));
+        let result = if source.is_dir {
+            builder.append_dir(&source.name, &source.path)
+        } else {
+            builder.append_path_with_name(&source.path, &source.name)
+        };
+        result.map_err(|e| format!("Failed to add {} to tar: {}", source.name, e))?;
+    }
+
+    builder.into_inner()
+        .map_err(|e| format!("Failed to finish tar: {}", e))
+}
+
+fn is_enclosed_link(source: &Source) -> bool {
+    match fs::read_link(&source.path) {
+        Ok(target) => link_is_enclosed(Path::new(&source.name), &target, true),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExtractionLimits;
+
+    #[test]
+    fn links_are_resolved_from_where_they_are() {
+        assert!(link_is_enclosed(Path::new("a/link"), Path::new("../b"), true));
+        assert!(!link_is_enclosed(Path::new("a/link"), Path::new("../../b"), true));
+        assert!(!link_is_enclosed(Path::new("link"), Path::new("/etc"), true));
+        assert!(link_is_enclosed(Path::new("a/b/link"), Path::new("a/file"), false));
+        assert!(!link_is_enclosed(Path::new("a/b/link"), Path::new("../file"), false));
+    }
+
+    #[test]
+    fn chained_links_look_enclosed_by_name() {
+        assert!(link_is_enclosed(Path::new("a/b/c/d/s"), Path::new("../../.."), true));
+        assert!(link_is_enclosed(Path::new("t"), Path::new("a/b/c/d/s/../../.."), true));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn chained_links_leading_out_are_removed() {
+        let dir = std::env::temp_dir().join(format!("fluxforge_tar_links_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let output = dir.join("out");
+        fs::create_dir_all(&output).unwrap();
+
+        let path = dir.join("chain.tar");
+        let mut builder = Builder::new(File::create(&path).unwrap());
+        let mut link = |name: &str, target: &str| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, name, target).unwrap();
+        };
+        link("a/b/c/d/s", "../../..");
+        link("t", "a/b/c/d/s/../../..");
+        builder.into_inner().unwrap();
+
+        let mut selection = Selection::new(&[], false);
+        let limits = ExtractionLimits::default();
+        let mut guard = Guard::new(&limits, &path, &output);
+        extract(&path.to_string_lossy(), &output, None, &mut selection, &mut guard).unwrap();
+
+        let exists = |name: &str| fs::symlink_metadata(output.join(name)).is_ok();
+        let kept = exists("a/b/c/d/s");
+        let removed = !exists("t");
+        let _ = fs::remove_dir_all(&dir);
+        assert!(kept);
+        assert!(removed);
+    }
+}
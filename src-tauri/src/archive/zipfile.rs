@@ -0,0 +1,227 @@
+use super::guard::Guard;
+use super::{EntryError, Selection, Source, TestRun};
+use crate::commands::ArchiveEntry;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, CompressionMethod, ZipArchive, ZipWriter};
+
+/// Files from this size on get Zip64 fields, leaving room below 4 GiB for
+/// Deflate to expand data that does not compress
+const ZIP64_THRESHOLD: u64 = 0xF000_0000;
+
+/// Most an entry may hold to be decrypted in telling a wrong password from
+/// a damaged entry
+const PASSWORD_CHECK_LIMIT: u64 = 16 * 1024 * 1024;
+
+/// Formats already compressed, stored as they are since Deflate would only
+/// spend time on them
+const COMPRESSED_EXTENSIONS: [&str; 27] = [
+    "jpg", "jpeg", "png", "gif", "webp", "heic", "avif",
+    "mp4", "mov", "m4v", "mkv", "webm", "avi",
+    "mp3", "m4a", "aac", "ogg", "opus", "flac",
+    "zip", "7z", "rar", "gz", "tgz", "bz2", "xz", "zst",
+];
+
+/// Extract the chosen entries of a ZIP archive, decrypting ZipCrypto and
+/// WinZip AES entries with `password`
+pub fn extract(
+    path: &str,
+    output: &Path,
+    password: Option<&str>,
+    selection: &mut Selection,
+    guard: &mut Guard,
+) -> Result<(), String> {
+    let file = fs::File::open(path)
+        .map_err(|e| format!("Failed to open zip file: {}", e))?;
+
+    let mut archive = ZipArchive::new(file)
+        .map_err(|e| format!("Failed to read zip archive: {}", e))?;
+
+    for i in 0..archive.len() {
+        // Decide from the central directory, so that entries left out are
+        // never decrypted
+        let (name, is_dir, size, encrypted) = {
+            let entry = archive.by_index_raw(i).map_err(|e| describe_error(e, path))?;
+            (entry.name().to_string(), entry.is_dir(), entry.size(), entry.encrypted())
+        };
+        let Some(relative) = selection.target(&name, is_dir) else {
+            continue;
+        };
+
+        let entry = match password {
+            Some(password) => archive.by_index_decrypt(i, password.as_bytes()),
+            None => archive.by_index(i),
+        };
+        let mut entry = entry.map_err(|e| describe_error(e, path))?;
+        guard.enter(&relative, is_dir, size)?;
+        let target = output.join(relative);
+
+        match super::write_entry(output, &target, is_dir, &mut entry, guard) {
+            Ok(()) => {}
+            Err(EntryError::Read(e)) => {
+                drop(entry);
+                return Err(match password {
+                    Some(password) if encrypted && !decrypts_elsewhere(&mut archive, password, i) => {
+                        super::wrong_password(path)
+                    }
+                    _ => format!("Corrupted zip entry {}: {}", name, e),
+                });
+            }
+            Err(EntryError::Write(message)) => return Err(message),
+        }
+    }
+
+    Ok(())
+}
+
+/// Entries of a ZIP archive as recorded in its central directory, which
+/// is never encrypted
+pub fn list(path: &str) -> Result<Vec<ArchiveEntry>, String> {
+    let file = fs::File::open(path)
+        .map_err(|e| format!("Failed to open zip file: {}", e))?;
+    let mut archive = ZipArchive::new(file)
+        .map_err(|e| format!("Failed to read zip archive: {}", e))?;
+
+    (0..archive.len())
+        .map(|i| {
+            let entry = archive.by_index_raw(i)
+                .map_err(|e| format!("Failed to read zip entry: {}", e))?;
+            let is_dir = entry.is_dir();
+            Ok(ArchiveEntry {
+                path: entry.name().to_string(),
+                is_dir,
+                size: entry.size(),
+                compressed_size: (!is_dir).then(|| entry.compressed_size()),
+                modified: entry.last_modified().and_then(|time| {
+                    super::dos_timestamp((time.datepart() as u32) << 16 | time.timepart() as u32)
+                }),
+                encrypted: entry.encrypted(),
+                crc: (!is_dir).then(|| entry.crc32()),
+                method: (!is_dir).then(|| entry.compression().to_string()),
+            })
+        })
+        .collect()
+}
+
+/// Decompress every file of a ZIP archive, which checks its CRC-32 and,
+/// for WinZip AES entries, its authentication code
+pub fn test(path: &str, password: Option<&str>) -> Result<TestRun, String> {
+    let file = fs::File::open(path)
+        .map_err(|e| format!("Failed to open zip file: {}", e))?;
+    let mut archive = ZipArchive::new(file)
+        .map_err(|e| format!("Failed to read zip archive: {}", e))?;
+
+    let mut run = TestRun::default();
+    for i in 0..archive.len() {
+        let (name, size, encrypted) = match archive.by_index_raw(i) {
+            Ok(entry) if entry.is_dir() => continue,
+            Ok(entry) => (entry.name().to_string(), entry.size(), entry.encrypted()),
+            Err(e) => return Err(format!("Failed to read zip entry: {}", e)),
+        };
+
+        let entry = match password {
+            Some(password) => archive.by_index_decrypt(i, password.as_bytes()),
+            None => archive.by_index(i),
+        };
+        let result = match entry {
+            Ok(mut entry) => io::copy(&mut entry, &mut io::sink())
+                .map(|_| ())
+                .map_err(|e| format!("Corrupted data: {}", e)),
+            Err(e @ (ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED) | ZipError::InvalidPassword)) => {
+                return Err(describe_error(e, path));
+            }
+            Err(e) => Err(format!("Failed to read zip entry: {}", e)),
+        };
+        run.record(name, size, encrypted, result);
+    }
+    Ok(run)
+}
+
+/// Create a ZIP archive, encrypting every entry with AES-256 when a password
+/// is given. Files are streamed in, so their size is not bounded by memory,
+/// and Zip64 is used where sizes or the entry count call for it.
+pub fn create(sources: &[Source], output: &Path, password: Option<&str>) -> Result<(), String> {
+    let file = fs::File::create(output)
+        .map_err(|e| format!("Failed to create zip file: {}", e))?;
+
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated);
+    let options = match password {
+        Some(password) => options.with_aes_encryption(AesMode::Aes256, password),
+        None => options,
+    };
+
+    for source in sources {
+        if source.is_dir {
+            zip.add_directory(source.name.as_str(), options)
+                .map_err(|e| format!("Failed to add zip folder: {}", e))?;
+            continue;
+        }
+
+        let mut file = fs::File::open(&source.path)
+            .map_err(|e| format!("Failed to open file: {}", e))?;
+        let size = file.metadata()
+            .map_err(|e| format!("Failed to read file info: {}", e))?
+            .len();
+
+        // Zip64 fields have to be reserved before the data is written
+        let method = if is_compressed_format(&source.path) {
+            CompressionMethod::Stored
+        } else {
+            CompressionMethod::Deflated
+        };
+        let entry_options = options
+            .compression_method(method)
+            .large_file(size >= ZIP64_THRESHOLD);
+        zip.start_file(source.name.as_str(), entry_options)
+            .map_err(|e| format!("Failed to start zip entry: {}", e))?;
+
+        io::copy(&mut file, &mut zip)
+            .map_err(|e| format!("Failed to write to zip: {}", e))?;
+    }
+
+    zip.finish()
+        .map_err(|e| format!("Failed to finish zip: {}", e))?;
+
+    Ok(())
+}
+
+/// ZipCrypto checks only one byte of the key up front, so a wrong password
+/// often gets as far as a bad checksum. It is taken to be right, and the
+/// failing entry damaged, if another encrypted file decrypts intact. Only
+/// the smallest is tried, and only when it is small enough to be quick.
+fn decrypts_elsewhere(archive: &mut ZipArchive<fs::File>, password: &str, failed: usize) -> bool {
+    let smallest = (0..archive.len())
+        .filter(|&i| i != failed)
+        .filter_map(|i| {
+            let entry = archive.by_index_raw(i).ok()?;
+            (entry.encrypted() && !entry.is_dir() && entry.size() > 0).then(|| (entry.size(), i))
+        })
+        .min();
+    let Some((size, i)) = smallest.filter(|&(size, _)| size <= PASSWORD_CHECK_LIMIT) else {
+        return false;
+    };
+    match archive.by_index_decrypt(i, password.as_bytes()) {
+        Ok(entry) => io::copy(&mut entry.take(PASSWORD_CHECK_LIMIT + 1), &mut io::sink())
+            .is_ok_and(|read| read == size),
+        Err(_) => false,
+    }
+}
+
+fn describe_error(error: ZipError, path: &str) -> String {
+    match error {
+        ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED) => super::password_required(path),
+        ZipError::InvalidPassword => super::wrong_password(path),
+        e => format!("Failed to read zip entry: {}", e),
+    }
+}
+
+fn is_compressed_format(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|extension| COMPRESSED_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
@@ -70,9 +70,217 @@ pub struct PdfInfo {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConvertOptions {
-    pub format: String,       // "jpg", "png", "svg"
+    pub format: String,       // "jpg", "png", "webp", "tiff"
     pub dpi: u32,
+    pub width: Option<u32>,   // Target size in pixels, used instead of dpi
+    pub height: Option<u32>,  // With width: fit inside width x height
     pub pages: Option<Vec<u32>>, // None = all pages
+    pub color_mode: Option<String>, // "rgb" (default), "gray", "mono"
+    pub threshold: Option<u8>, // Mono: luma below this becomes black (default 128)
+    #[serde(default)]
+    pub dither: bool,         // Mono: Floyd-Steinberg dithering instead of threshold
+    #[serde(default)]
+    pub transparent: bool,    // PNG/WebP: transparent instead of white background
+    pub antialias: Option<bool>, // Text, image and path smoothing (default on)
+    pub quality: Option<u8>,  // JPEG and lossy WebP quality, 1-100
+    #[serde(default)]
+    pub lossless: bool,       // WebP
+    pub tiff_compression: Option<String>, // "none", "lzw" (default), "deflate", "g4" (default for mono, mono only)
+    #[serde(default)]
+    pub multi_page: bool,     // TIFF: one file per PDF
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OverlayOptions {
+    pub position: String,     // "under" (background), "over" (stamp)
+    #[serde(default)]
+    pub first_page_only: bool,
+    #[serde(default)]
+    pub cycle: bool,          // Use stationery pages in turn; otherwise its first page throughout
+    pub scale: Option<String>, // "fit" (default), "stretch", "none" (actual size, top-left aligned)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OcrOptions {
+    #[serde(default)]
+    pub languages: Vec<String>, // Tesseract language packs, e.g. ["jpn", "eng"] (the default)
+    pub dpi: Option<u32>,     // Rendering resolution for recognition (default 300)
+    pub pages: Option<Vec<u32>>, // None = all pages
+    #[serde(default)]
+    pub force: bool,          // Also OCR pages that already have text
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ThumbnailOptions {
+    pub pages: Option<Vec<u32>>, // None = all pages
+    pub size: u32,            // Longest side in pixels
+    pub output: String,       // "base64", "file"
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PageThumbnail {
+    pub page: u32,
+    pub width: u32,
+    pub height: u32,
+    pub data: Option<String>, // PNG data URL when output = "base64"
+    pub path: Option<String>, // Cached PNG file when output = "file"
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchOptions {
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub ignore_width: bool,   // Treat full-width and half-width forms as equal
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchRect {
+    pub x: f32,               // Points from the top-left corner of the page as shown
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub page: u32,
+    pub text: String,
+    pub context: String,
+    pub rects: Vec<SearchRect>,
+    pub page_width: f32,
+    pub page_height: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub path: String,
+    pub hits: Vec<SearchHit>,
+    pub truncated: bool,      // Hit limit reached
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PdfOutputOptions {
+    pub linearize: bool,      // Fast web view
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PdfaViolation {
+    pub rule: String,         // "fonts", "transparency", "metadata", ...
+    pub message: String,
+    pub page: Option<u32>,    // None = document-wide
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PdfaReport {
+    pub path: String,
+    pub conformant: bool,
+    pub violations: Vec<PdfaViolation>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PdfFont {
+    pub name: String,         // Without the subset prefix
+    pub base_font: String,    // As stored, e.g. "ABCDEF+MS-Gothic"
+    pub font_type: String,    // "Type1", "TrueType", "Type3", "Type0/CIDFontType2", ...
+    pub embedded: bool,
+    pub subset: bool,
+    pub encoding: Option<String>, // None = the font's built-in encoding
+    pub pages: Vec<u32>,      // Pages using the font
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FontReport {
+    pub path: String,
+    pub fonts: Vec<PdfFont>,
+    pub not_embedded: u32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PdfSignature {
+    pub field: String,        // Fully qualified field name
+    pub page: Option<u32>,
+    pub signed: bool,         // False for an empty signature field
+    pub sub_filter: Option<String>, // "adbe.pkcs7.detached", "ETSI.CAdES.detached", ...
+    pub signer_name: Option<String>,
+    pub subject: Option<String>,    // Signer certificate, e.g. "CN=..., O=..., C=JP"
+    pub issuer: Option<String>,
+    pub signing_time: Option<String>, // ISO 8601, as claimed by the signer
+    pub timestamp: Option<String>,  // ISO 8601, from an embedded timestamp token
+    pub reason: Option<String>,
+    pub location: Option<String>,
+    pub digest_algorithm: Option<String>,
+    pub byte_range: Vec<i64>,
+    pub intact: Option<bool>, // Signed bytes match the signed digest; None = cannot be checked
+    pub modified_after_signing: bool, // The file continues past the signed range
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignatureReport {
+    pub path: String,
+    pub signatures: Vec<PdfSignature>,
+    pub modified: bool,       // Any signature broken or followed by changes
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompareOptions {
+    pub dpi: u32,
+    pub tolerance: u8,        // Per-channel difference treated as noise
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PageDiff {
+    pub page: u32,
+    pub status: String,       // "identical", "changed", "added", "removed"
+    pub changed_percent: f64,
+    pub text_added: Vec<String>,
+    pub text_removed: Vec<String>,
+    pub diff_image: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompareResult {
+    pub success: bool,
+    pub output_files: Vec<String>,
+    pub output_folder: String,
+    pub message: String,
+    pub pages: Vec<PageDiff>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PdfLink {
+    pub page: u32,
+    pub kind: String,         // "internal", "uri", "file", "named", "javascript", "other"
+    pub target: String,       // URI, destination name or linked file
+    pub dest_page: Option<u32>, // Resolved page of internal destinations
+    pub rect: Option<[f32; 4]>, // Link area in points: left, bottom, right, top
+    pub broken: bool,         // Destination or linked file not found
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutlineEntry {
+    pub level: u32,           // 1 = top level
+    pub title: String,
+    pub kind: Option<String>, // None = entry without a destination
+    pub target: Option<String>,
+    pub dest_page: Option<u32>,
+    pub broken: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LinkReport {
+    pub path: String,
+    pub links: Vec<PdfLink>,
+    pub outline: Vec<OutlineEntry>,
+    pub broken: u32,          // Broken links and outline entries
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LinkReportResult {
+    pub success: bool,
+    pub output_files: Vec<String>,
+    pub output_folder: String,
+    pub message: String,
+    pub reports: Vec<LinkReport>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -88,6 +296,25 @@ pub fn get_pdf_info(path: String) -> Result<PdfInfo, String> {
     pdf::get_info(&path)
 }
 
+#[tauri::command]
+pub async fn get_pdf_thumbnails(
+    app: AppHandle,
+    path: String,
+    options: ThumbnailOptions,
+) -> Result<Vec<PageThumbnail>, String> {
+    let cache_dir = config::get_cache_path(&app, "thumbnails")?;
+    pdf::thumbnails(&cache_dir, &path, &options)
+}
+
+#[tauri::command]
+pub async fn search_pdf(
+    path: String,
+    query: String,
+    options: SearchOptions,
+) -> Result<SearchResult, String> {
+    pdf::search(&path, &query, &options)
+}
+
 #[tauri::command]
 pub async fn convert_pdf_to_images(
     app: AppHandle,
@@ -113,9 +340,10 @@ pub async fn merge_pdfs(
     app: AppHandle,
     paths: Vec<String>,
     output_name: String,
+    output: Option<PdfOutputOptions>,
 ) -> Result<ConvertResult, String> {
     let config = config::load_config(&app)?;
-    pdf::merge(&config, &paths, &output_name).await
+    pdf::merge(&config, &paths, &output_name, &output.unwrap_or_default()).await
 }
 
 #[tauri::command]
@@ -128,15 +356,95 @@ pub async fn split_pdf(
     pdf::split(&config, &path, &split_points).await
 }
 
+#[tauri::command]
+pub async fn check_pdfa(path: String) -> Result<PdfaReport, String> {
+    pdf::check_pdfa(&path)
+}
+
+#[tauri::command]
+pub async fn list_pdf_fonts(path: String) -> Result<FontReport, String> {
+    pdf::font_report(&path)
+}
+
+#[tauri::command]
+pub async fn inspect_pdf_signatures(path: String) -> Result<SignatureReport, String> {
+    pdf::inspect_signatures(&path)
+}
+
+#[tauri::command]
+pub async fn convert_to_pdfa(
+    app: AppHandle,
+    paths: Vec<String>,
+) -> Result<ConvertResult, String> {
+    let config = config::load_config(&app)?;
+    pdf::convert_to_pdfa(&config, &paths).await
+}
+
+#[tauri::command]
+pub async fn compare_pdfs(
+    app: AppHandle,
+    path_a: String,
+    path_b: String,
+    options: CompareOptions,
+) -> Result<CompareResult, String> {
+    let config = config::load_config(&app)?;
+    pdf::compare(&config, &path_a, &path_b, &options).await
+}
+
+#[tauri::command]
+pub async fn overlay_pdf(
+    app: AppHandle,
+    paths: Vec<String>,
+    stationery: String,
+    options: OverlayOptions,
+    output: Option<PdfOutputOptions>,
+) -> Result<ConvertResult, String> {
+    let config = config::load_config(&app)?;
+    pdf::overlay(&config, &paths, &stationery, &options, &output.unwrap_or_default()).await
+}
+
+#[tauri::command]
+pub async fn ocr_pdf(
+    app: AppHandle,
+    paths: Vec<String>,
+    options: OcrOptions,
+    output: Option<PdfOutputOptions>,
+) -> Result<ConvertResult, String> {
+    let config = config::load_config(&app)?;
+    let work_dir = config::get_cache_path(&app, "ocr")?;
+    pdf::ocr(&config, &work_dir, &paths, &options, &output.unwrap_or_default()).await
+}
+
+#[tauri::command]
+pub async fn extract_pdf_links(
+    app: AppHandle,
+    paths: Vec<String>,
+    format: String,           // "csv", "json"
+) -> Result<LinkReportResult, String> {
+    let config = config::load_config(&app)?;
+    pdf::extract_links(&config, &paths, &format).await
+}
+
 #[tauri::command]
 pub async fn extract_pdf_pages(
     app: AppHandle,
     path: String,
     pages: Vec<u32>,
     output_name: String,
+    output: Option<PdfOutputOptions>,
 ) -> Result<ConvertResult, String> {
     let config = config::load_config(&app)?;
-    pdf::extract_pages(&config, &path, &pages, &output_name).await
+    pdf::extract_pages(&config, &path, &pages, &output_name, &output.unwrap_or_default()).await
+}
+
+#[tauri::command]
+pub async fn optimize_pdf(
+    app: AppHandle,
+    paths: Vec<String>,
+    output: Option<PdfOutputOptions>,
+) -> Result<ConvertResult, String> {
+    let config = config::load_config(&app)?;
+    pdf::optimize(&config, &paths, &output.unwrap_or_default()).await
 }
 
 // ============================================
@@ -145,8 +453,72 @@ pub async fn extract_pdf_pages(
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ArchiveOptions {
-    pub format: String,       // "zip", "7z"
+    pub format: String,       // "zip", "7z", "tar", "tar.gz", "tar.bz2", "tar.xz", "tar.zst"
     pub password: Option<String>,
+    pub level: Option<u32>,   // 7z/xz: 0-9 (default 6), gzip: 0-9 (6), bzip2: 1-9 (9), zstd: 1-22 (3)
+    pub solid: Option<bool>,  // 7z: all files in one solid block (default true)
+    #[serde(default)]
+    pub encrypt_names: bool,  // 7z: also encrypt file names when a password is set
+    #[serde(default)]
+    pub include: Vec<String>, // Glob patterns for files to take from folders, e.g. ["*.pdf"]; empty takes all
+    #[serde(default)]
+    pub exclude: Vec<String>, // Glob patterns to leave out, e.g. ["node_modules", ".git", "Thumbs.db"]
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompressOptions {
+    pub format: String,       // "gz", "bz2", "xz", "zst", "lz4"
+    pub level: Option<u32>,   // gzip: 0-9 (default 6), bzip2: 1-9 (9), xz: 0-9 (6), zstd: 1-22 (3), lz4: 1-12 (1)
+    pub threads: Option<u32>, // xz/zstd: worker threads (default: one per CPU)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ExtractOptions {
+    #[serde(default)]
+    pub entries: Vec<String>, // Entry paths or glob patterns to extract, e.g. ["docs", "*.pdf"]; empty extracts all
+    #[serde(default)]
+    pub flatten: bool,        // Put every file straight into the output folder, dropping its folders
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub path: String,                 // Path inside the archive, '/'-separated
+    pub is_dir: bool,
+    pub size: u64,                    // Uncompressed size in bytes
+    pub compressed_size: Option<u64>, // None for tar, RAR, and files in 7z solid blocks
+    pub modified: Option<String>,     // ISO 8601; ZIP and RAR times carry no zone
+    pub encrypted: bool,
+    pub crc: Option<u32>,             // CRC-32 of the contents
+    pub method: Option<String>,       // e.g. "Deflated", "LZMA2", "Normal"
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveListing {
+    pub path: String,
+    pub format: String,            // "zip", "7z", "rar", "tar", "tar.gz", ...
+    pub entries: Vec<ArchiveEntry>, // Sorted by path, with folders implied by paths added
+    pub total_size: u64,           // Uncompressed size of all files
+    pub file_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveEntryTest {
+    pub path: String,
+    pub size: u64,
+    pub encrypted: bool,
+    pub passed: bool,
+    pub error: Option<String>,     // Why the file failed, e.g. a CRC mismatch
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveTestReport {
+    pub path: String,
+    pub format: String,
+    pub entries: Vec<ArchiveEntryTest>, // Files only, folders have nothing to check
+    pub passed: usize,
+    pub failed: usize,
+    pub error: Option<String>,     // Damage that stopped the test before the end of the archive
+    pub intact: bool,              // Every file passed and the whole archive was read
 }
 
 #[tauri::command]
@@ -154,9 +526,10 @@ pub async fn extract_archive(
     app: AppHandle,
     path: String,
     password: Option<String>,
+    options: Option<ExtractOptions>,
 ) -> Result<ConvertResult, String> {
     let config = config::load_config(&app)?;
-    archive::extract(&config, &path, password).await
+    archive::extract(&config, &path, password, &options.unwrap_or_default()).await
 }
 
 #[tauri::command]
@@ -170,6 +543,32 @@ pub async fn create_archive(
     archive::create(&config, &paths, &output_name, &options).await
 }
 
+#[tauri::command]
+pub async fn list_archive(path: String, password: Option<String>) -> Result<ArchiveListing, String> {
+    archive::list(&path, password.as_deref().filter(|p| !p.is_empty()))
+}
+
+#[tauri::command]
+pub async fn test_archive(path: String, password: Option<String>) -> Result<ArchiveTestReport, String> {
+    archive::test(&path, password.as_deref().filter(|p| !p.is_empty()))
+}
+
+#[tauri::command]
+pub async fn compress_files(
+    app: AppHandle,
+    paths: Vec<String>,
+    options: CompressOptions,
+) -> Result<ConvertResult, String> {
+    let config = config::load_config(&app)?;
+    archive::compress(&config, &paths, &options).await
+}
+
+#[tauri::command]
+pub async fn decompress_files(app: AppHandle, paths: Vec<String>) -> Result<ConvertResult, String> {
+    let config = config::load_config(&app)?;
+    archive::decompress(&config, &paths).await
+}
+
 // ============================================
 // Video Commands
 // ============================================
@@ -11,6 +11,33 @@ pub struct AppConfig {
     pub default_pdf_dpi: u32,
     pub auto_create_date_folders: bool,
     pub cloud_sync_folder: Option<String>,
+    #[serde(default)]
+    pub extraction_limits: ExtractionLimits,
+}
+
+/// Limits on what extracting an archive may produce, so that an archive
+/// crafted to expand without end (a zip bomb) cannot fill the disk. Zero
+/// turns a limit off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractionLimits {
+    pub max_total_size: u64,  // Bytes extracted from one archive
+    pub max_ratio: u64,       // Extracted size to archive size, e.g. 1000 for 1000:1
+    pub max_entries: u64,     // Files and folders in one archive
+    pub max_depth: u32,       // Folders nested inside each other; archives inside are not opened
+    pub min_free_space: u64,  // Bytes to leave free on the disk
+}
+
+impl Default for ExtractionLimits {
+    fn default() -> Self {
+        Self {
+            max_total_size: 20 * 1024 * 1024 * 1024,
+            max_ratio: 1000,
+            max_entries: 100_000,
+            max_depth: 64,
+            min_free_space: 1024 * 1024 * 1024,
+        }
+    }
 }
 
 impl Default for AppConfig {
@@ -22,14 +49,16 @@ impl Default for AppConfig {
             default_pdf_dpi: 150,
             auto_create_date_folders: true,
             cloud_sync_folder: None,
+            extraction_limits: ExtractionLimits::default(),
         }
     }
 }
 
 /// Category folder names
-pub const CATEGORY_FOLDERS: [&str; 4] = [
+pub const CATEGORY_FOLDERS: [&str; 5] = [
     "PDF_Images",
     "PDF_Operations", 
+    "PDF_Reports",
     "Archives",
     "GIF",
 ];
@@ -142,3 +171,15 @@ pub fn get_output_path(config: &AppConfig, category: &str) -> Result<PathBuf, St
         Ok(base_path)
     }
 }
+
+/// Get a folder under the app cache dir, creating it if needed
+pub fn get_cache_path(app: &AppHandle, name: &str) -> Result<PathBuf, String> {
+    let cache_dir = app.path().app_cache_dir()
+        .map_err(|e| format!("Failed to get app cache dir: {}", e))?
+        .join(name);
+    
+    fs::create_dir_all(&cache_dir)
+        .map_err(|e| format!("Failed to create cache dir: {}", e))?;
+    
+    Ok(cache_dir)
+}
@@ -27,11 +27,26 @@ fn main() {
             commands::merge_pdfs,
             commands::split_pdf,
             commands::extract_pdf_pages,
+            commands::optimize_pdf,
+            commands::overlay_pdf,
+            commands::ocr_pdf,
             commands::get_pdf_info,
+            commands::get_pdf_thumbnails,
+            commands::search_pdf,
+            commands::check_pdfa,
+            commands::convert_to_pdfa,
+            commands::list_pdf_fonts,
+            commands::inspect_pdf_signatures,
+            commands::compare_pdfs,
+            commands::extract_pdf_links,
             
             // Archive commands
             commands::extract_archive,
             commands::create_archive,
+            commands::list_archive,
+            commands::test_archive,
+            commands::compress_files,
+            commands::decompress_files,
             
             // Video commands
             commands::convert_video_to_gif,
@@ -1,8 +1,78 @@
-use crate::commands::{ConvertOptions, ConvertResult, PdfInfo};
+use crate::commands::{ConvertOptions, ConvertResult, PdfInfo, PdfOutputOptions};
 use crate::config::{self, AppConfig};
+use copy::PageCollector;
+use lopdf::Document;
 use std::path::Path;
 use std::fs;
 
+mod compare;
+mod copy;
+mod export;
+mod fonts;
+mod linearize;
+mod links;
+mod ocr;
+mod overlay;
+mod pdfa;
+mod raster;
+mod render;
+mod search;
+mod signatures;
+mod text;
+mod thumbnail;
+mod writer;
+
+pub use compare::compare;
+pub use fonts::font_report;
+pub use links::extract_links;
+pub use ocr::ocr;
+pub use overlay::overlay;
+pub use pdfa::{check_pdfa, convert_to_pdfa};
+pub use search::search;
+pub use signatures::inspect_signatures;
+pub use thumbnail::thumbnails;
+
+/// Load a PDF for structural inspection or editing
+fn load_document(path: &str) -> Result<Document, String> {
+    if !Path::new(path).exists() {
+        return Err(format!("File not found: {}", path));
+    }
+
+    Document::load(path)
+        .map_err(|e| format!("Failed to read PDF: {}", e))
+}
+
+/// Load a PDF for editing, removing encryption that needs no password
+fn load_decrypted(path: &str) -> Result<Document, String> {
+    let mut doc = load_document(path)?;
+
+    if doc.is_encrypted() {
+        doc.decrypt("")
+            .map_err(|_| format!("Encrypted PDF requires a password: {}", path))?;
+    }
+
+    Ok(doc)
+}
+
+/// Write an edited document, linearized if requested
+fn save_document(doc: &mut Document, path: &Path, output: &PdfOutputOptions) -> Result<(), String> {
+    writer::ensure_file_id(doc);
+
+    if output.linearize {
+        linearize::save(doc, path)
+    } else {
+        writer::save(doc, path)
+    }
+}
+
+fn output_file_name(output_name: &str) -> String {
+    if output_name.ends_with(".pdf") {
+        output_name.to_string()
+    } else {
+        format!("{}.pdf", output_name)
+    }
+}
+
 /// Get PDF information
 pub fn get_info(path: &str) -> Result<PdfInfo, String> {
     let file_path = Path::new(path);
@@ -25,34 +95,20 @@ pub fn get_info(path: &str) -> Result<PdfInfo, String> {
     })
 }
 
-/// Convert PDF to images (JPG, PNG)
+/// Convert PDF to images (JPG, PNG, WebP, TIFF)
 pub async fn convert_to_images(
     config: &AppConfig,
     paths: &[String],
     options: &ConvertOptions,
 ) -> Result<ConvertResult, String> {
     let output_folder = config::get_output_path(config, "PDF_Images")?;
-    let mut output_files = Vec::new();
-    
-    for path in paths {
-        let file_path = Path::new(path);
-        let stem = file_path.file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("output");
-        
-        // TODO: Implement actual PDF to image conversion
-        // This requires poppler-rs or pdfium for proper rendering
-        // For now, create placeholder result
-        
-        let pages = options.pages.clone().unwrap_or_else(|| vec![1]);
-        
-        for page in pages {
-            let output_name = format!("{}_{}.{}", stem, page, options.format);
-            let output_path = output_folder.join(&output_name);
-            output_files.push(output_path.to_string_lossy().to_string());
-        }
+    raster::extension(&options.format)?;
+    if options.width == Some(0) || options.height == Some(0) {
+        return Err("Image width and height must be greater than zero".to_string());
     }
     
+    let output_files = export::render_images(paths, options, &output_folder)?;
+    
     Ok(ConvertResult {
         success: true,
         output_files,
@@ -61,6 +117,19 @@ pub async fn convert_to_images(
     })
 }
 
+/// Validate requested 1-based page numbers; None selects every page
+fn select_pages(page_count: u32, pages: Option<&[u32]>) -> Result<Vec<u32>, String> {
+    match pages {
+        None => Ok((1..=page_count).collect()),
+        Some(pages) => {
+            if let Some(&page) = pages.iter().find(|&&page| page == 0 || page > page_count) {
+                return Err(format!("Page {} does not exist (document has {} pages)", page, page_count));
+            }
+            Ok(pages.to_vec())
+        }
+    }
+}
+
 /// Convert PDF to SVG (vector)
 pub async fn convert_to_svg(
     config: &AppConfig,
@@ -98,18 +167,24 @@ pub async fn merge(
     config: &AppConfig,
     paths: &[String],
     output_name: &str,
+    output: &PdfOutputOptions,
 ) -> Result<ConvertResult, String> {
     let output_folder = config::get_output_path(config, "PDF_Operations")?;
+    let output_path = output_folder.join(output_file_name(output_name));
     
-    let output_filename = if output_name.ends_with(".pdf") {
-        output_name.to_string()
-    } else {
-        format!("{}.pdf", output_name)
-    };
+    let mut collector = PageCollector::new();
+    for path in paths {
+        let source = load_decrypted(path)?;
+        let page_numbers: Vec<u32> = source.get_pages().into_keys().collect();
+        collector.import_pages(&source, &page_numbers)?;
+    }
     
-    let output_path = output_folder.join(&output_filename);
+    if collector.page_count() == 0 {
+        return Err("No pages to merge".to_string());
+    }
     
-    // TODO: Implement actual PDF merge using lopdf crate
+    let mut doc = collector.finish();
+    save_document(&mut doc, &output_path, output)?;
     
     Ok(ConvertResult {
         success: true,
@@ -159,18 +234,21 @@ pub async fn extract_pages(
     path: &str,
     pages: &[u32],
     output_name: &str,
+    output: &PdfOutputOptions,
 ) -> Result<ConvertResult, String> {
-    let output_folder = config::get_output_path(config, "PDF_Operations")?;
+    if pages.is_empty() {
+        return Err("No pages selected".to_string());
+    }
     
-    let output_filename = if output_name.ends_with(".pdf") {
-        output_name.to_string()
-    } else {
-        format!("{}.pdf", output_name)
-    };
+    let output_folder = config::get_output_path(config, "PDF_Operations")?;
+    let output_path = output_folder.join(output_file_name(output_name));
     
-    let output_path = output_folder.join(&output_filename);
+    let source = load_decrypted(path)?;
+    let mut collector = PageCollector::new();
+    collector.import_pages(&source, pages)?;
     
-    // TODO: Implement actual page extraction using lopdf crate
+    let mut doc = collector.finish();
+    save_document(&mut doc, &output_path, output)?;
     
     Ok(ConvertResult {
         success: true,
@@ -179,3 +257,37 @@ pub async fn extract_pages(
         message: format!("{}ページを抽出しました", pages.len()),
     })
 }
+
+/// Shrink PDFs by dropping unused objects and compressing streams
+///
+/// Linearized output was asked for on merge, extract and optimize; this is
+/// the optimize step, which had no command of its own to carry the option.
+pub async fn optimize(
+    config: &AppConfig,
+    paths: &[String],
+    output: &PdfOutputOptions,
+) -> Result<ConvertResult, String> {
+    let output_folder = config::get_output_path(config, "PDF_Operations")?;
+    let mut output_files = Vec::new();
+    
+    for path in paths {
+        let mut doc = load_decrypted(path)?;
+        writer::prune_unreachable(&mut doc);
+        doc.compress();
+        
+        let stem = Path::new(path).file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        let output_path = output_folder.join(format!("{}_optimized.pdf", stem));
+        save_document(&mut doc, &output_path, output)?;
+        
+        output_files.push(output_path.to_string_lossy().to_string());
+    }
+    
+    Ok(ConvertResult {
+        success: true,
+        output_files,
+        output_folder: output_folder.to_string_lossy().to_string(),
+        message: format!("{}個のPDFを最適化しました", paths.len()),
+    })
+}
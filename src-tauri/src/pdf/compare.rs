@@ -0,0 +1,202 @@
+use super::{render, text};
+use crate::commands::{CompareOptions, CompareResult, PageDiff};
+use crate::config::{self, AppConfig};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::fs;
+use std::path::Path;
+
+/// Diff highlight color
+const HIGHLIGHT: Rgba<u8> = Rgba([230, 30, 30, 255]);
+
+/// Line-level diffs above this many comparisons fall back to set difference
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+/// Highest resolution pages are rendered at for comparison
+const MAX_DPI: u32 = 600;
+
+/// Compare two PDFs page by page, visually and by extracted text
+pub async fn compare(
+    config: &AppConfig,
+    path_a: &str,
+    path_b: &str,
+    options: &CompareOptions,
+) -> Result<CompareResult, String> {
+    if options.dpi == 0 || options.dpi > MAX_DPI {
+        return Err(format!("Comparison resolution must be between 1 and {} DPI", MAX_DPI));
+    }
+    let output_folder = config::get_output_path(config, "PDF_Reports")?;
+
+    let doc_a = render::open(path_a)?;
+    let doc_b = render::open(path_b)?;
+
+    let prefix = format!("{}_vs_{}", file_stem(path_a), file_stem(path_b));
+    let count_a = doc_a.pages().len();
+    let count_b = doc_b.pages().len();
+
+    let mut pages = Vec::new();
+    let mut output_files = Vec::new();
+
+    for index in 0..count_a.max(count_b) {
+        let page_a = if index < count_a {
+            Some(doc_a.pages().get(index).map_err(|e| format!("Failed to read page: {}", e))?)
+        } else {
+            None
+        };
+        let page_b = if index < count_b {
+            Some(doc_b.pages().get(index).map_err(|e| format!("Failed to read page: {}", e))?)
+        } else {
+            None
+        };
+
+        let page_number = index as u32 + 1;
+        let text_a = page_a.as_ref().map(text::page_text).transpose()?.unwrap_or_default();
+        let text_b = page_b.as_ref().map(text::page_text).transpose()?.unwrap_or_default();
+        let (text_removed, text_added) = diff_lines(&text_a, &text_b);
+
+        let (status, changed_percent, diff_image) = match (&page_a, &page_b) {
+            (Some(a), Some(b)) => {
+                // Both pages at one resolution, so that they line up
+                let dpi = render::capped_dpi(a, options.dpi).min(render::capped_dpi(b, options.dpi));
+                let image_a = render::render_page(a, dpi)?;
+                let image_b = render::render_page(b, dpi)?;
+                let (diff, changed) = diff_images(&image_a, &image_b, options.tolerance);
+                let total = (diff.width() as u64 * diff.height() as u64).max(1);
+                let percent = changed as f64 * 100.0 / total as f64;
+
+                if changed > 0 {
+                    let diff_path = output_folder.join(format!("{}_p{}_diff.png", prefix, page_number));
+                    diff.save(&diff_path)
+                        .map_err(|e| format!("Failed to save diff image: {}", e))?;
+                    let diff_path = diff_path.to_string_lossy().to_string();
+                    output_files.push(diff_path.clone());
+                    ("changed", percent, Some(diff_path))
+                } else if !text_added.is_empty() || !text_removed.is_empty() {
+                    ("changed", percent, None)
+                } else {
+                    ("identical", percent, None)
+                }
+            }
+            (Some(_), None) => ("removed", 100.0, None),
+            _ => ("added", 100.0, None),
+        };
+
+        pages.push(PageDiff {
+            page: page_number,
+            status: status.to_string(),
+            changed_percent: (changed_percent * 100.0).round() / 100.0,
+            text_added,
+            text_removed,
+            diff_image,
+        });
+    }
+
+    let summary_path = output_folder.join(format!("{}_summary.json", prefix));
+    let summary = serde_json::to_string_pretty(&pages)
+        .map_err(|e| format!("Failed to serialize summary: {}", e))?;
+    fs::write(&summary_path, summary)
+        .map_err(|e| format!("Failed to write summary: {}", e))?;
+    output_files.push(summary_path.to_string_lossy().to_string());
+
+    let changed = pages.iter().filter(|p| p.status != "identical").count();
+    let message = if changed == 0 {
+        "差分はありません".to_string()
+    } else {
+        format!("{}ページ中{}ページに差分があります", pages.len(), changed)
+    };
+
+    Ok(CompareResult {
+        success: true,
+        output_files,
+        output_folder: output_folder.to_string_lossy().to_string(),
+        message,
+        pages,
+    })
+}
+
+fn file_stem(path: &str) -> &str {
+    Path::new(path).file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output")
+}
+
+/// Build a highlighted diff image and count differing pixels.
+///
+/// Pages of different sizes are compared on a white canvas covering both.
+/// The second document is drawn faded underneath the highlighted pixels.
+fn diff_images(a: &DynamicImage, b: &DynamicImage, tolerance: u8) -> (RgbaImage, u64) {
+    let a = a.to_rgba8();
+    let b = b.to_rgba8();
+    let width = a.width().max(b.width());
+    let height = a.height().max(b.height());
+    let white = Rgba([255, 255, 255, 255]);
+
+    let mut diff = RgbaImage::new(width, height);
+    let mut changed = 0u64;
+
+    for y in 0..height {
+        for x in 0..width {
+            let pa = if x < a.width() && y < a.height() { *a.get_pixel(x, y) } else { white };
+            let pb = if x < b.width() && y < b.height() { *b.get_pixel(x, y) } else { white };
+
+            let distance = (0..3)
+                .map(|c| (pa[c] as i16 - pb[c] as i16).unsigned_abs())
+                .max()
+                .unwrap_or(0);
+
+            if distance > tolerance as u16 {
+                changed += 1;
+                diff.put_pixel(x, y, HIGHLIGHT);
+            } else {
+                let luma = (pb[0] as u32 * 299 + pb[1] as u32 * 587 + pb[2] as u32 * 114) / 1000;
+                let faded = (255 - (255 - luma) / 4) as u8;
+                diff.put_pixel(x, y, Rgba([faded, faded, faded, 255]));
+            }
+        }
+    }
+
+    (diff, changed)
+}
+
+/// Lines removed from `a` and added in `b`, by longest common subsequence
+fn diff_lines(a: &str, b: &str) -> (Vec<String>, Vec<String>) {
+    let lines_a: Vec<&str> = a.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    let lines_b: Vec<&str> = b.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+
+    if lines_a.len() * lines_b.len() > MAX_LCS_CELLS {
+        let removed = lines_a.iter().filter(|l| !lines_b.contains(l)).map(|l| l.to_string()).collect();
+        let added = lines_b.iter().filter(|l| !lines_a.contains(l)).map(|l| l.to_string()).collect();
+        return (removed, added);
+    }
+
+    let (n, m) = (lines_a.len(), lines_b.len());
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if lines_a[i] == lines_b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if lines_a[i] == lines_b[j] {
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            removed.push(lines_a[i].to_string());
+            i += 1;
+        } else {
+            added.push(lines_b[j].to_string());
+            j += 1;
+        }
+    }
+    removed.extend(lines_a[i..].iter().map(|l| l.to_string()));
+    added.extend(lines_b[j..].iter().map(|l| l.to_string()));
+
+    (removed, added)
+}
@@ -0,0 +1,540 @@
+use super::writer;
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Page attributes that may be inherited from the page tree
+const INHERITABLE: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// Limit on name tree nesting, against cyclic or malformed trees
+const MAX_NAME_TREE_DEPTH: usize = 32;
+
+/// Builds a new document from pages copied out of other documents.
+///
+/// Each page is copied together with everything it references (resources,
+/// content streams, annotations). Objects shared between pages of the same
+/// source are copied once per `import_pages` call.
+///
+/// The outline, named destinations, form fields and document information
+/// come along too, less whatever leads to pages that were left out.
+pub struct PageCollector {
+    doc: Document,
+    pages: Vec<ObjectId>,
+    info: Option<Object>,
+    outlines: Vec<ObjectId>,          // Copied outline root of each source
+    dests: Dictionary,                // Catalog Dests, by name
+    names: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Object>>, // Name trees by kind, e.g. Dests, EmbeddedFiles
+    acroform: Option<Dictionary>,     // Form dictionary of the first source, without its fields
+    fields: Vec<ObjectId>,
+}
+
+impl PageCollector {
+    pub fn new() -> Self {
+        Self {
+            doc: Document::with_version("1.7"),
+            pages: Vec::new(),
+            info: None,
+            outlines: Vec::new(),
+            dests: Dictionary::new(),
+            names: BTreeMap::new(),
+            acroform: None,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Copy the given 1-based pages of `source`, in the order given
+    pub fn import_pages(&mut self, source: &Document, page_numbers: &[u32]) -> Result<(), String> {
+        let source_pages = source.get_pages();
+        let mut copier = ObjectCopier::new(source);
+
+        let mut selected = Vec::with_capacity(page_numbers.len());
+        for number in page_numbers {
+            let page_id = *source_pages.get(number)
+                .ok_or_else(|| format!("Page {} does not exist (document has {} pages)", number, source_pages.len()))?;
+            selected.push(page_id);
+        }
+
+        // Reserve ids first so links between imported pages keep pointing at the copies
+        for &page_id in &selected {
+            copier.ids.entry(page_id).or_insert_with(|| self.doc.new_object_id());
+        }
+        copier.page_ids = selected.iter().copied().collect();
+
+        for &page_id in &selected {
+            let new_id = copier.ids[&page_id];
+            if self.doc.objects.contains_key(&new_id) {
+                // The same page was requested twice; give the repeat its own page object
+                let page = self.doc.get_object(new_id).cloned()
+                    .map_err(|e| format!("Failed to copy page: {}", e))?;
+                let duplicate = self.doc.add_object(page);
+                self.pages.push(duplicate);
+                continue;
+            }
+
+            let mut page = inherited_page(source, page_id)?;
+            page.remove(b"Parent");
+            let page = copier.copy(&mut self.doc, &Object::Dictionary(page));
+            self.doc.objects.insert(new_id, page);
+            self.pages.push(new_id);
+        }
+
+        self.import_catalog(source, &mut copier);
+        Ok(())
+    }
+
+    /// Copy the document-level parts of `source`. Names taken by an earlier
+    /// source are kept, and later ones with the same name dropped.
+    fn import_catalog(&mut self, source: &Document, copier: &mut ObjectCopier) {
+        if self.info.is_none() {
+            if let Ok(info) = source.trailer.get(b"Info") {
+                self.info = match copier.copy(&mut self.doc, info) {
+                    Object::Null => None,
+                    Object::Reference(id) => Some(Object::Reference(id)),
+                    direct => Some(Object::Reference(self.doc.add_object(direct))),
+                };
+            }
+        }
+
+        let Ok(catalog) = source.catalog() else {
+            return;
+        };
+
+        if let Ok(outlines) = catalog.get(b"Outlines") {
+            if let Object::Reference(id) = copier.copy(&mut self.doc, outlines) {
+                self.outlines.push(id);
+            }
+        }
+
+        if let Ok(dests) = catalog.get_deref(b"Dests", source).and_then(Object::as_dict) {
+            for (name, dest) in dests.iter() {
+                if !self.dests.has(name) {
+                    let dest = copier.copy(&mut self.doc, dest);
+                    self.dests.set(name.clone(), dest);
+                }
+            }
+        }
+
+        if let Ok(names) = catalog.get_deref(b"Names", source).and_then(Object::as_dict) {
+            for (kind, tree) in names.iter() {
+                let Some(tree) = source.dereference(tree).ok().and_then(|(_, tree)| tree.as_dict().ok()) else {
+                    continue;
+                };
+                let mut entries = Vec::new();
+                name_tree_entries(source, tree, &mut entries, 0);
+                for (key, value) in entries {
+                    if !self.names.get(kind).is_some_and(|copied| copied.contains_key(&key)) {
+                        let value = copier.copy(&mut self.doc, value);
+                        self.names.entry(kind.clone()).or_default().insert(key, value);
+                    }
+                }
+            }
+        }
+
+        if let Ok(form) = catalog.get_deref(b"AcroForm", source).and_then(Object::as_dict) {
+            if let Ok(fields) = form.get_deref(b"Fields", source).and_then(Object::as_array) {
+                for field in fields {
+                    if let Object::Reference(id) = copier.copy(&mut self.doc, field) {
+                        self.fields.push(id);
+                    }
+                }
+            }
+            if self.acroform.is_none() {
+                let mut form = form.clone();
+                form.remove(b"Fields");
+                self.acroform = Some(copier.copy_dictionary(&mut self.doc, &form));
+            }
+        }
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Assemble the page tree and catalog
+    pub fn finish(mut self) -> Document {
+        let pages_id = self.doc.new_object_id();
+
+        for &page_id in &self.pages {
+            if let Ok(page) = self.doc.get_dictionary_mut(page_id) {
+                page.set("Parent", pages_id);
+            }
+        }
+
+        let kids: Vec<Object> = self.pages.iter().map(|&id| Object::Reference(id)).collect();
+        self.doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => self.pages.len() as i64,
+        }));
+
+        let mut catalog = dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        };
+
+        // Destinations that lost their page are dropped, then everything
+        // pointing at them by name
+        let mut dests = Dictionary::new();
+        for (name, dest) in self.dests.iter() {
+            if leads_to_page(&self.doc, dest, &HashSet::new()) {
+                dests.set(name.clone(), dest.clone());
+            }
+        }
+        if let Some(tree) = self.names.get_mut(&b"Dests"[..]) {
+            let doc = &self.doc;
+            tree.retain(|_, dest| leads_to_page(doc, dest, &HashSet::new()));
+        }
+        let named: HashSet<Vec<u8>> = dests.iter().map(|(name, _)| name.clone())
+            .chain(self.names.get(&b"Dests"[..]).into_iter().flat_map(|tree| tree.keys().cloned()))
+            .collect();
+        if !dests.is_empty() {
+            catalog.set("Dests", self.doc.add_object(dests));
+        }
+
+        let names: Dictionary = self.names.into_iter()
+            .filter(|(_, tree)| !tree.is_empty())
+            .map(|(kind, tree)| {
+                let pairs: Vec<Object> = tree.into_iter()
+                    .flat_map(|(key, value)| [Object::string_literal(key), value])
+                    .collect();
+                (kind, Object::Dictionary(dictionary! { "Names" => pairs }))
+            })
+            .collect();
+        if !names.is_empty() {
+            catalog.set("Names", self.doc.add_object(names));
+        }
+
+        let mut seen = HashSet::new();
+        let items: Vec<ObjectId> = self.outlines.iter()
+            .flat_map(|&root| outline_children(&self.doc, root, &mut seen))
+            .collect();
+        let outlines_id = self.doc.add_object(dictionary! { "Type" => "Outlines" });
+        let count = link_outline(&mut self.doc, outlines_id, items, &named, &mut seen);
+        if self.doc.get_dictionary(outlines_id).is_ok_and(|outlines| outlines.has(b"First")) {
+            if let Ok(outlines) = self.doc.get_dictionary_mut(outlines_id) {
+                outlines.set("Count", count);
+            }
+            catalog.set("Outlines", outlines_id);
+        }
+
+        let widgets: HashSet<ObjectId> = self.pages.iter()
+            .filter_map(|&page_id| self.doc.get_dictionary(page_id).ok())
+            .filter_map(|page| page.get(b"Annots").and_then(Object::as_array).ok())
+            .flatten()
+            .filter_map(|annot| annot.as_reference().ok())
+            .collect();
+        let mut seen = HashSet::new();
+        let fields: Vec<Object> = self.fields.iter()
+            .copied()
+            .filter(|&field| keep_field(&mut self.doc, field, &widgets, &mut seen))
+            .map(Object::Reference)
+            .collect();
+        if let Some(mut form) = self.acroform.take().filter(|_| !fields.is_empty()) {
+            form.set("Fields", fields);
+            catalog.set("AcroForm", self.doc.add_object(form));
+        }
+
+        let catalog_id = self.doc.add_object(catalog);
+        self.doc.trailer.set("Root", catalog_id);
+        if let Some(info) = self.info {
+            self.doc.trailer.set("Info", info);
+        }
+
+        // Leaves out what only led to pages that were not copied
+        writer::prune_unreachable(&mut self.doc);
+        self.doc
+    }
+}
+
+impl Default for PageCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Copies object graphs from one document into another, remapping ids
+pub struct ObjectCopier<'a> {
+    source: &'a Document,
+    ids: HashMap<ObjectId, ObjectId>,
+    page_ids: HashSet<ObjectId>,
+}
+
+impl<'a> ObjectCopier<'a> {
+    pub fn new(source: &'a Document) -> Self {
+        Self {
+            source,
+            ids: HashMap::new(),
+            page_ids: HashSet::new(),
+        }
+    }
+
+    /// Deep-copy `object` into `target`, returning the copy.
+    ///
+    /// References to pages that are not being copied become null, so a link
+    /// annotation never drags a whole foreign page into the target.
+    pub fn copy(&mut self, target: &mut Document, object: &Object) -> Object {
+        match object {
+            Object::Reference(id) => match self.copy_reference(target, *id) {
+                Some(new_id) => Object::Reference(new_id),
+                None => Object::Null,
+            },
+            Object::Array(items) => Object::Array(items.iter().map(|item| self.copy(target, item)).collect()),
+            Object::Dictionary(dict) => Object::Dictionary(self.copy_dictionary(target, dict)),
+            Object::Stream(stream) => {
+                let mut copy = stream.clone();
+                copy.dict = self.copy_dictionary(target, &stream.dict);
+                Object::Stream(copy)
+            }
+            other => other.clone(),
+        }
+    }
+
+    fn copy_dictionary(&mut self, target: &mut Document, dict: &Dictionary) -> Dictionary {
+        let mut copy = Dictionary::new();
+        for (key, value) in dict.iter() {
+            if key.as_slice() == b"Parent" && is_page_tree_reference(self.source, value) {
+                continue;
+            }
+            copy.set(key.clone(), self.copy(target, value));
+        }
+        copy
+    }
+
+    fn copy_reference(&mut self, target: &mut Document, id: ObjectId) -> Option<ObjectId> {
+        if let Some(&new_id) = self.ids.get(&id) {
+            return Some(new_id);
+        }
+
+        let object = self.source.get_object(id).ok()?;
+        if is_page_node(object) && !self.page_ids.contains(&id) {
+            return None;
+        }
+
+        let new_id = target.new_object_id();
+        self.ids.insert(id, new_id);
+        let copy = self.copy(target, object);
+        target.objects.insert(new_id, copy);
+        Some(new_id)
+    }
+}
+
+/// Whether a destination still leads to a page: explicit ones lose their
+/// page (it becomes null) when it was not copied, named ones their name
+fn leads_to_page(doc: &Document, dest: &Object, named: &HashSet<Vec<u8>>) -> bool {
+    let Ok((_, dest)) = doc.dereference(dest) else {
+        return false;
+    };
+    let dest = match dest {
+        Object::Dictionary(dict) => match dict.get_deref(b"D", doc) {
+            Ok(dest) => dest,
+            Err(_) => return false,
+        },
+        dest => dest,
+    };
+    match dest {
+        Object::Array(items) => items.first().is_some_and(|page| !matches!(page, Object::Null)),
+        Object::Name(name) | Object::String(name, _) => named.contains(name),
+        _ => false,
+    }
+}
+
+/// Whether an outline item still leads somewhere. Actions other than
+/// going to a page in this document are left alone.
+fn outline_target_kept(doc: &Document, item: &Dictionary, named: &HashSet<Vec<u8>>) -> bool {
+    if let Ok(dest) = item.get(b"Dest") {
+        return leads_to_page(doc, dest, named);
+    }
+    match item.get_deref(b"A", doc).and_then(Object::as_dict) {
+        Ok(action) if action.get(b"S").and_then(Object::as_name).ok() == Some(b"GoTo") => {
+            action.get(b"D").is_ok_and(|dest| leads_to_page(doc, dest, named))
+        }
+        _ => true,
+    }
+}
+
+/// The items directly under an outline item or root, in order
+fn outline_children(doc: &Document, id: ObjectId, seen: &mut HashSet<ObjectId>) -> Vec<ObjectId> {
+    let mut children = Vec::new();
+    let mut next = doc.get_dictionary(id).ok()
+        .and_then(|node| node.get(b"First").and_then(Object::as_reference).ok());
+    // `seen` guards against Next/First chains that loop back on themselves
+    while let Some(child) = next.filter(|&child| seen.insert(child)) {
+        let Ok(item) = doc.get_dictionary(child) else {
+            break;
+        };
+        children.push(child);
+        next = item.get(b"Next").and_then(Object::as_reference).ok();
+    }
+    children
+}
+
+/// Chain `items` under `parent`, dropping those that lead to a page that
+/// was not copied. An item that still has children keeps its place as a
+/// plain heading. Returns how many items show under `parent` when open.
+fn link_outline(
+    doc: &mut Document,
+    parent: ObjectId,
+    items: Vec<ObjectId>,
+    named: &HashSet<Vec<u8>>,
+    seen: &mut HashSet<ObjectId>,
+) -> i64 {
+    let mut kept = Vec::new();
+    let mut shown = 0;
+    for id in items {
+        let children = outline_children(doc, id, seen);
+        let descendants = link_outline(doc, id, children, named, seen);
+        let Ok(item) = doc.get_dictionary(id) else {
+            continue;
+        };
+        let dangling = !outline_target_kept(doc, item, named);
+        let has_children = item.has(b"First");
+        if dangling && !has_children {
+            continue;
+        }
+
+        let open = item.get(b"Count").and_then(Object::as_i64).is_ok_and(|count| count > 0);
+        let Ok(item) = doc.get_dictionary_mut(id) else {
+            continue;
+        };
+        if dangling {
+            item.remove(b"Dest");
+            item.remove(b"A");
+        }
+        item.set("Parent", parent);
+        if has_children {
+            item.set("Count", if open { descendants } else { -descendants });
+        } else {
+            item.remove(b"Count");
+        }
+        shown += 1 + if open { descendants } else { 0 };
+        kept.push(id);
+    }
+
+    for (i, &id) in kept.iter().enumerate() {
+        if let Ok(item) = doc.get_dictionary_mut(id) {
+            item.remove(b"Prev");
+            item.remove(b"Next");
+            if i > 0 {
+                item.set("Prev", kept[i - 1]);
+            }
+            if let Some(&next) = kept.get(i + 1) {
+                item.set("Next", next);
+            }
+        }
+    }
+    if let Ok(node) = doc.get_dictionary_mut(parent) {
+        match (kept.first(), kept.last()) {
+            (Some(&first), Some(&last)) => {
+                node.set("First", first);
+                node.set("Last", last);
+            }
+            _ => {
+                node.remove(b"First");
+                node.remove(b"Last");
+            }
+        }
+    }
+    shown
+}
+
+/// Whether a form field still has a widget on a copied page, dropping the
+/// children that do not
+fn keep_field(
+    doc: &mut Document,
+    field: ObjectId,
+    widgets: &HashSet<ObjectId>,
+    seen: &mut HashSet<ObjectId>,
+) -> bool {
+    if !seen.insert(field) {
+        return false;
+    }
+    let kids: Option<Vec<ObjectId>> = doc.get_dictionary(field).ok()
+        .and_then(|field| field.get(b"Kids").and_then(Object::as_array).ok())
+        .map(|kids| kids.iter().filter_map(|kid| kid.as_reference().ok()).collect());
+    let Some(kids) = kids else {
+        return widgets.contains(&field);
+    };
+
+    let kept: Vec<Object> = kids.into_iter()
+        .filter(|&kid| keep_field(doc, kid, widgets, seen))
+        .map(Object::Reference)
+        .collect();
+    let keep = !kept.is_empty();
+    if let Ok(field) = doc.get_dictionary_mut(field) {
+        field.set("Kids", kept);
+    }
+    keep
+}
+
+/// Every key and value of a name tree, in the order stored
+fn name_tree_entries<'a>(
+    doc: &'a Document,
+    node: &'a Dictionary,
+    entries: &mut Vec<(Vec<u8>, &'a Object)>,
+    depth: usize,
+) {
+    if depth > MAX_NAME_TREE_DEPTH {
+        return;
+    }
+
+    if let Ok(pairs) = node.get_deref(b"Names", doc).and_then(Object::as_array) {
+        for pair in pairs.chunks(2) {
+            if let [key, value] = pair {
+                if let Ok(key) = key.as_str() {
+                    entries.push((key.to_vec(), value));
+                }
+            }
+        }
+    }
+
+    if let Ok(kids) = node.get_deref(b"Kids", doc).and_then(Object::as_array) {
+        for kid in kids {
+            if let Some(kid) = doc.dereference(kid).ok().and_then(|(_, kid)| kid.as_dict().ok()) {
+                name_tree_entries(doc, kid, entries, depth + 1);
+            }
+        }
+    }
+}
+
+fn is_page_node(object: &Object) -> bool {
+    object.as_dict().is_ok_and(|dict| dict.type_is(b"Page") || dict.type_is(b"Pages"))
+}
+
+fn is_page_tree_reference(doc: &Document, value: &Object) -> bool {
+    value.as_reference().ok()
+        .and_then(|id| doc.get_object(id).ok())
+        .is_some_and(is_page_node)
+}
+
+/// Page dictionary with inherited attributes resolved onto it
+pub fn inherited_page(doc: &Document, page_id: ObjectId) -> Result<Dictionary, String> {
+    let mut page = doc.get_dictionary(page_id)
+        .map_err(|e| format!("Failed to read page: {}", e))?
+        .clone();
+
+    let mut parent = page.get(b"Parent").and_then(Object::as_reference).ok();
+    let mut depth = 0;
+    while let Some(parent_id) = parent {
+        let node = match doc.get_dictionary(parent_id) {
+            Ok(node) => node,
+            Err(_) => break,
+        };
+        for key in INHERITABLE {
+            if !page.has(key) {
+                if let Ok(value) = node.get(key) {
+                    page.set(key, value.clone());
+                }
+            }
+        }
+        parent = node.get(b"Parent").and_then(Object::as_reference).ok();
+        depth += 1;
+        if depth > 64 {
+            break;
+        }
+    }
+
+    if !page.has(b"MediaBox") {
+        page.set("MediaBox", vec![0.into(), 0.into(), 612.into(), 792.into()]);
+    }
+
+    Ok(page)
+}
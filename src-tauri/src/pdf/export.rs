@@ -0,0 +1,362 @@
+use super::{raster, render};
+use crate::commands::ConvertOptions;
+use image::DynamicImage;
+use pdfium_render::prelude::PdfDocument;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// Upper bound on encoder threads
+const MAX_WORKERS: usize = 8;
+
+/// Rendered pages waiting to be encoded are held to this many bytes
+const MAX_PENDING_BYTES: usize = 512 * 1024 * 1024;
+
+/// A rendered page on its way to the encoders
+struct Job {
+    seq: usize,
+    image: DynamicImage,
+    dpi: u32,
+    target: Target,
+}
+
+enum Target {
+    /// One image file per page
+    File(PathBuf),
+    /// A page of a multi-page TIFF; the last page closes the file
+    TiffPage { path: PathBuf, last: bool },
+}
+
+/// An encoded page, or a prepared one the collector still has to write
+enum Done {
+    Written(PathBuf),
+    TiffPage {
+        path: PathBuf,
+        image: DynamicImage,
+        dpi: u32,
+        last: bool,
+        bytes: usize,
+    },
+}
+
+/// Memory held by rendered pages that are not written out yet.
+///
+/// Rendering waits here when the encoders fall behind, and stops
+/// altogether once the conversion has failed.
+struct Budget {
+    state: Mutex<BudgetState>,
+    changed: Condvar,
+}
+
+struct BudgetState {
+    used: usize,
+    aborted: bool,
+}
+
+impl Budget {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(BudgetState { used: 0, aborted: false }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Wait for room for `bytes`; false once the conversion has been aborted.
+    /// A page larger than the whole budget still goes through on its own.
+    fn acquire(&self, bytes: usize) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        while !state.aborted && state.used > 0 && state.used + bytes > MAX_PENDING_BYTES {
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        state.used += bytes;
+        !state.aborted
+    }
+
+    fn release(&self, bytes: usize) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.used = state.used.saturating_sub(bytes);
+        self.changed.notify_all();
+    }
+
+    fn abort(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).aborted = true;
+        self.changed.notify_all();
+    }
+
+    fn is_aborted(&self) -> bool {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).aborted
+    }
+}
+
+/// Aborts the conversion when the thread holding it panics, so that no
+/// other thread is left waiting on memory the panicking one held
+struct AbortOnPanic<'a>(&'a Budget);
+
+impl Drop for AbortOnPanic<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.abort();
+        }
+    }
+}
+
+/// Render pages of every PDF into `output_folder`, returning the written
+/// files in input order.
+///
+/// PDFium only renders one page at a time, so pages are rendered on this
+/// thread while a pool of workers converts, encodes and writes them.
+pub fn render_images(
+    paths: &[String],
+    options: &ConvertOptions,
+    output_folder: &Path,
+) -> Result<Vec<String>, String> {
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_WORKERS);
+    let budget = Budget::new();
+    let (job_sender, job_receiver) = mpsc::sync_channel::<Job>(workers);
+    let job_receiver = Mutex::new(job_receiver);
+    let (done_sender, done_receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let done_sender = done_sender.clone();
+            let (job_receiver, budget) = (&job_receiver, &budget);
+            scope.spawn(move || {
+                let _abort = AbortOnPanic(budget);
+                loop {
+                    let job = match job_receiver.lock().map(|receiver| receiver.recv()) {
+                        Ok(Ok(job)) => job,
+                        _ => break,
+                    };
+                    let seq = job.seq;
+                    let result = encode_page(job, options, budget);
+                    // Keep draining after the collector gives up, so rendering
+                    // never blocks on a full queue
+                    let _ = done_sender.send((seq, result));
+                }
+            });
+        }
+        drop(done_sender);
+
+        let collector = scope.spawn(|| {
+            let _abort = AbortOnPanic(&budget);
+            collect(done_receiver, options, &budget)
+        });
+        let _abort = AbortOnPanic(&budget);
+        let rendered = render_pages(paths, options, output_folder, &budget, job_sender);
+        if rendered.is_err() {
+            budget.abort();
+        }
+
+        let collected = collector.join()
+            .unwrap_or_else(|_| Err("Image export failed".to_string()));
+        rendered.and(collected)
+    })
+}
+
+/// Render every selected page in order and hand it to the workers
+fn render_pages(
+    paths: &[String],
+    options: &ConvertOptions,
+    output_folder: &Path,
+    budget: &Budget,
+    jobs: SyncSender<Job>,
+) -> Result<(), String> {
+    let extension = raster::extension(&options.format)?;
+    let mut seq = 0;
+
+    for path in paths {
+        let stem = Path::new(path).file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+
+        let doc = render::open(path)?;
+        let pages = super::select_pages(doc.pages().len() as u32, options.pages.as_deref())?;
+        // With multi_page, all pages of this PDF go into one file
+        let multi_page = options.multi_page && extension == "tiff";
+
+        for (index, &page) in pages.iter().enumerate() {
+            let (image, dpi) = render_page(&doc, page, options)?;
+            let target = if multi_page {
+                Target::TiffPage {
+                    path: output_folder.join(format!("{}.{}", stem, extension)),
+                    last: index + 1 == pages.len(),
+                }
+            } else {
+                Target::File(output_folder.join(format!("{}_{}.{}", stem, page, extension)))
+            };
+
+            if !budget.acquire(image.as_bytes().len()) {
+                return Ok(());
+            }
+            if jobs.send(Job { seq, image, dpi, target }).is_err() {
+                return Ok(());
+            }
+            seq += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// Render one page, along with the resolution to record in the file
+fn render_page(doc: &PdfDocument, page: u32, options: &ConvertOptions) -> Result<(DynamicImage, u32), String> {
+    let pdf_page = doc.pages().get((page - 1) as u16)
+        .map_err(|e| format!("Failed to read page: {}", e))?;
+    let image = render::render_for_export(&pdf_page, options)?;
+
+    // Taken from the pixels, since a pixel-size target or the pixel limit
+    // may have set the scale instead of the DPI asked for
+    let dpi = (image.width() as f32 * 72.0 / pdf_page.width().value).round().max(1.0) as u32;
+
+    Ok((image, dpi))
+}
+
+/// Worker side: convert a page and write it out, or pass multi-page TIFF
+/// pages on to the collector, which appends them in order
+fn encode_page(job: Job, options: &ConvertOptions, budget: &Budget) -> Result<Done, String> {
+    let bytes = job.image.as_bytes().len();
+    if budget.is_aborted() {
+        budget.release(bytes);
+        return Err("Image export aborted".to_string());
+    }
+
+    let result = raster::prepare(job.image, options).and_then(|image| match job.target {
+        Target::File(path) => {
+            let data = raster::encode(&image, job.dpi, options)?;
+            fs::write(&path, data)
+                .map_err(|e| format!("Failed to write image: {}", e))?;
+            Ok(Done::Written(path))
+        }
+        Target::TiffPage { path, last } => Ok(Done::TiffPage {
+            path,
+            image,
+            dpi: job.dpi,
+            last,
+            bytes,
+        }),
+    });
+
+    if !matches!(result, Ok(Done::TiffPage { .. })) {
+        budget.release(bytes);
+    }
+    result
+}
+
+/// Put finished pages back into render order and assemble multi-page TIFFs
+fn collect(
+    done: Receiver<(usize, Result<Done, String>)>,
+    options: &ConvertOptions,
+    budget: &Budget,
+) -> Result<Vec<String>, String> {
+    let mut waiting = BTreeMap::new();
+    let mut next = 0;
+    let mut output_files = Vec::new();
+    let mut tiff: Option<raster::TiffWriter<File>> = None;
+
+    for (seq, result) in done {
+        waiting.insert(seq, result);
+
+        while let Some(result) = waiting.remove(&next) {
+            next += 1;
+            let step = result.and_then(|done| match done {
+                Done::Written(path) => {
+                    output_files.push(path.to_string_lossy().to_string());
+                    Ok(())
+                }
+                Done::TiffPage { path, image, dpi, last, bytes } => {
+                    let written = append_tiff_page(&mut tiff, &path, &image, dpi, options);
+                    budget.release(bytes);
+                    if last {
+                        tiff = None;
+                        output_files.push(path.to_string_lossy().to_string());
+                    }
+                    written
+                }
+            });
+
+            if let Err(e) = step {
+                budget.abort();
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(output_files)
+}
+
+fn append_tiff_page(
+    tiff: &mut Option<raster::TiffWriter<File>>,
+    path: &Path,
+    image: &DynamicImage,
+    dpi: u32,
+    options: &ConvertOptions,
+) -> Result<(), String> {
+    let writer = match tiff {
+        Some(writer) => writer,
+        None => {
+            let file = File::create(path)
+                .map_err(|e| format!("Failed to write image: {}", e))?;
+            tiff.insert(raster::TiffWriter::new(file, options)?)
+        }
+    };
+    writer.add_page(image, dpi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn page_larger_than_budget_goes_through_alone() {
+        let budget = Budget::new();
+        assert!(budget.acquire(MAX_PENDING_BYTES * 2));
+        budget.release(MAX_PENDING_BYTES * 2);
+        assert!(budget.acquire(1));
+    }
+
+    #[test]
+    fn acquire_waits_for_release() {
+        let budget = Budget::new();
+        assert!(budget.acquire(MAX_PENDING_BYTES));
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| budget.acquire(1));
+            thread::sleep(Duration::from_millis(50));
+            assert!(!waiting.is_finished());
+            budget.release(MAX_PENDING_BYTES);
+            assert!(waiting.join().unwrap());
+        });
+    }
+
+    #[test]
+    fn abort_wakes_waiting_pages() {
+        let budget = Budget::new();
+        assert!(budget.acquire(MAX_PENDING_BYTES));
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| budget.acquire(1));
+            thread::sleep(Duration::from_millis(50));
+            budget.abort();
+            assert!(!waiting.join().unwrap());
+        });
+        assert!(budget.is_aborted());
+        assert!(!budget.acquire(1));
+    }
+
+    #[test]
+    fn panic_aborts_conversion() {
+        let budget = Budget::new();
+        thread::scope(|scope| {
+            let panicked = scope.spawn(|| {
+                let _abort = AbortOnPanic(&budget);
+                panic!("encoder failed");
+            });
+            assert!(panicked.join().is_err());
+        });
+        assert!(budget.is_aborted());
+    }
+}
@@ -0,0 +1,219 @@
+use super::copy;
+use crate::commands::{FontReport, PdfFont};
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::{HashMap, HashSet};
+
+/// Form XObjects nested deeper than this are not searched for fonts
+const MAX_FORM_DEPTH: usize = 16;
+
+/// Fonts are told apart by object; fonts written inline in a resource
+/// dictionary have no object number and are told apart by name
+#[derive(PartialEq, Eq, Hash)]
+enum FontKey {
+    Object(ObjectId),
+    Inline(String),
+}
+
+/// List every font used on each page, flagging the ones not embedded
+pub fn font_report(path: &str) -> Result<FontReport, String> {
+    let doc = super::load_decrypted(path)?;
+    let mut fonts: Vec<PdfFont> = Vec::new();
+    let mut index: HashMap<FontKey, usize> = HashMap::new();
+
+    for (page_number, page_id) in doc.get_pages() {
+        let page = copy::inherited_page(&doc, page_id)?;
+        let mut found = Vec::new();
+        let mut seen = HashSet::new();
+
+        if let Ok(resources) = page.get_deref(b"Resources", &doc).and_then(Object::as_dict) {
+            collect_fonts(&doc, resources, &mut found, &mut seen, 0);
+        }
+        // Form fields and other annotations draw with fonts of their own
+        for annotation in doc.get_page_annotations(page_id).unwrap_or_default() {
+            for appearance in appearance_streams(&doc, annotation) {
+                if let Ok(resources) = appearance.get_deref(b"Resources", &doc).and_then(Object::as_dict) {
+                    collect_fonts(&doc, resources, &mut found, &mut seen, 1);
+                }
+            }
+        }
+
+        for (key, font) in found {
+            let position = *index.entry(key).or_insert_with(|| {
+                fonts.push(describe(&doc, font));
+                fonts.len() - 1
+            });
+            let pages = &mut fonts[position].pages;
+            if pages.last() != Some(&page_number) {
+                pages.push(page_number);
+            }
+        }
+    }
+
+    fonts.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.base_font.cmp(&b.base_font)));
+    let not_embedded = fonts.iter().filter(|font| !font.embedded).count() as u32;
+
+    Ok(FontReport {
+        path: path.to_string(),
+        fonts,
+        not_embedded,
+    })
+}
+
+/// Fonts of a resource dictionary, including those of Form XObjects and
+/// Type 3 glyph procedures it draws
+fn collect_fonts<'a>(
+    doc: &'a Document,
+    resources: &'a Dictionary,
+    found: &mut Vec<(FontKey, &'a Dictionary)>,
+    seen: &mut HashSet<ObjectId>,
+    depth: usize,
+) {
+    if depth > MAX_FORM_DEPTH {
+        return;
+    }
+
+    if let Ok(font_dict) = resources.get_deref(b"Font", doc).and_then(Object::as_dict) {
+        for (_, font) in font_dict.iter() {
+            let Ok((id, Object::Dictionary(font))) = doc.dereference(font) else {
+                continue;
+            };
+            let key = match id {
+                Some(id) => FontKey::Object(id),
+                None => FontKey::Inline(format!("{}/{}", base_font(font), name(font, b"Subtype"))),
+            };
+            if id.is_some_and(|id| !seen.insert(id)) {
+                continue;
+            }
+
+            if font.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type3") {
+                if let Ok(glyph_resources) = font.get_deref(b"Resources", doc).and_then(Object::as_dict) {
+                    collect_fonts(doc, glyph_resources, found, seen, depth + 1);
+                }
+            }
+            found.push((key, font));
+        }
+    }
+
+    if let Ok(xobjects) = resources.get_deref(b"XObject", doc).and_then(Object::as_dict) {
+        for (_, xobject) in xobjects.iter() {
+            let Ok(id) = xobject.as_reference() else {
+                continue;
+            };
+            if !seen.insert(id) {
+                continue;
+            }
+            let Ok(Object::Stream(form)) = doc.get_object(id) else {
+                continue;
+            };
+            if form.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Form") {
+                continue;
+            }
+            if let Ok(form_resources) = form.dict.get_deref(b"Resources", doc).and_then(Object::as_dict) {
+                collect_fonts(doc, form_resources, found, seen, depth + 1);
+            }
+        }
+    }
+}
+
+/// Normal appearance streams of an annotation; widgets with on/off states
+/// have one per state
+fn appearance_streams<'a>(doc: &'a Document, annotation: &'a Dictionary) -> Vec<&'a Dictionary> {
+    let normal = annotation.get_deref(b"AP", doc)
+        .and_then(Object::as_dict)
+        .and_then(|appearance| appearance.get_deref(b"N", doc));
+
+    match normal {
+        Ok(Object::Stream(stream)) => vec![&stream.dict],
+        Ok(Object::Dictionary(states)) => states.iter()
+            .filter_map(|(_, state)| match doc.dereference(state) {
+                Ok((_, Object::Stream(stream))) => Some(&stream.dict),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn describe(doc: &Document, font: &Dictionary) -> PdfFont {
+    let base_font = base_font(font);
+    let subtype = name(font, b"Subtype");
+    let subset = is_subset(&base_font);
+
+    let descendant = font.get_deref(b"DescendantFonts", doc)
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|descendants| descendants.first())
+        .and_then(|descendant| doc.dereference(descendant).ok())
+        .and_then(|(_, descendant)| descendant.as_dict().ok());
+
+    let (font_type, embedded) = match (subtype.as_str(), descendant) {
+        // Type 3 glyphs are content streams inside the PDF itself
+        ("Type3", _) => (subtype.clone(), true),
+        (_, Some(descendant)) => (
+            format!("{}/{}", subtype, name(descendant, b"Subtype")),
+            is_embedded(doc, descendant),
+        ),
+        _ => (subtype.clone(), is_embedded(doc, font)),
+    };
+
+    PdfFont {
+        name: if subset { base_font[7..].to_string() } else { base_font.clone() },
+        base_font,
+        font_type,
+        embedded,
+        subset,
+        encoding: encoding(doc, font),
+        pages: Vec::new(),
+    }
+}
+
+/// Whether a simple or CID font carries its font program
+pub(super) fn is_embedded(doc: &Document, font: &Dictionary) -> bool {
+    font.get_deref(b"FontDescriptor", doc)
+        .and_then(Object::as_dict)
+        .is_ok_and(|descriptor| {
+            descriptor.has(b"FontFile") || descriptor.has(b"FontFile2") || descriptor.has(b"FontFile3")
+        })
+}
+
+/// Subset fonts are named with a six capital letter tag, "ABCDEF+Name"
+fn is_subset(base_font: &str) -> bool {
+    let bytes = base_font.as_bytes();
+    bytes.len() > 7 && bytes[6] == b'+' && bytes[..6].iter().all(u8::is_ascii_uppercase)
+}
+
+fn base_font(font: &Dictionary) -> String {
+    // Type 3 fonts need no BaseFont, but may carry a Name
+    match name(font, b"BaseFont") {
+        base if base.is_empty() => name(font, b"Name"),
+        base => base,
+    }
+}
+
+fn name(dict: &Dictionary, key: &[u8]) -> String {
+    dict.get(key)
+        .and_then(Object::as_name)
+        .map(|name| String::from_utf8_lossy(name).to_string())
+        .unwrap_or_default()
+}
+
+/// Encoding as named in the font; None when it uses its built-in encoding
+fn encoding(doc: &Document, font: &Dictionary) -> Option<String> {
+    match font.get_deref(b"Encoding", doc).ok()? {
+        Object::Name(encoding) => Some(String::from_utf8_lossy(encoding).to_string()),
+        Object::Dictionary(encoding) => {
+            let base = name(encoding, b"BaseEncoding");
+            Some(match (base.is_empty(), encoding.has(b"Differences")) {
+                (true, _) => "Custom".to_string(),
+                (false, true) => format!("{} (Differences)", base),
+                (false, false) => base,
+            })
+        }
+        // Embedded CMap of a composite font
+        Object::Stream(cmap) => match name(&cmap.dict, b"CMapName") {
+            cmap_name if cmap_name.is_empty() => Some("Embedded CMap".to_string()),
+            cmap_name => Some(cmap_name),
+        },
+        _ => None,
+    }
+}
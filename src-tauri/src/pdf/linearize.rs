@@ -0,0 +1,602 @@
+use super::writer;
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Catalog entries that belong to the document-level part of the file (ISO 32000-1 F.3.4)
+const DOCUMENT_LEVEL_KEYS: [&[u8]; 4] = [b"ViewerPreferences", b"Threads", b"OpenAction", b"AcroForm"];
+
+/// Serialize a document as a linearized ("fast web view") PDF.
+///
+/// The file is laid out as described in ISO 32000-1 Annex F: the catalog
+/// and everything needed for the first page come first, followed by the
+/// remaining pages in order, objects shared between pages, and everything
+/// else. A hint stream tells viewers where each page's objects live so the
+/// first page can be shown before the whole file has been downloaded.
+pub fn save(doc: &Document, path: &Path) -> Result<(), String> {
+    let mut doc = doc.clone();
+    writer::prune_unreachable(&mut doc);
+
+    let layout = Layout::plan(&doc)?;
+    let buffer = layout.write(&doc);
+
+    fs::write(path, buffer)
+        .map_err(|e| format!("Failed to write PDF: {}", e))
+}
+
+/// Object order and numbering of the linearized file
+struct Layout {
+    /// Catalog and document-level objects (part 4)
+    document_part: Vec<ObjectId>,
+    /// First page object followed by everything it uses (part 6)
+    first_page_part: Vec<ObjectId>,
+    /// Later pages: page object, then objects private to that page (part 7)
+    page_parts: Vec<Vec<ObjectId>>,
+    /// Objects shared by later pages, not already in the first page (part 8)
+    shared_part: Vec<ObjectId>,
+    /// Everything else (part 9)
+    other_part: Vec<ObjectId>,
+    /// Shared objects referenced by each later page
+    page_shared: Vec<Vec<ObjectId>>,
+    numbers: HashMap<ObjectId, u32>,
+}
+
+impl Layout {
+    fn plan(doc: &Document) -> Result<Self, String> {
+        let catalog_id = doc.trailer.get(b"Root")
+            .and_then(Object::as_reference)
+            .map_err(|_| "PDF has no document catalog".to_string())?;
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        if pages.is_empty() {
+            return Err("PDF has no pages".to_string());
+        }
+
+        // Objects reachable from each page without passing through another page
+        let page_sets: Vec<Vec<ObjectId>> = pages.iter()
+            .map(|&page_id| page_objects(doc, page_id, catalog_id))
+            .collect();
+
+        let mut usage: HashMap<ObjectId, usize> = HashMap::new();
+        for set in &page_sets {
+            for &id in set {
+                *usage.entry(id).or_insert(0) += 1;
+            }
+        }
+
+        let mut document_part = vec![catalog_id];
+        let mut assigned: HashSet<ObjectId> = HashSet::from([catalog_id]);
+        if let Ok(catalog) = doc.get_dictionary(catalog_id) {
+            let mut pending = Vec::new();
+            for key in DOCUMENT_LEVEL_KEYS {
+                if let Ok(value) = catalog.get(key) {
+                    collect_references(value, &mut pending);
+                }
+            }
+            pending.reverse();
+            while let Some(id) = pending.pop() {
+                if usage.contains_key(&id) || assigned.contains(&id) {
+                    continue;
+                }
+                let Ok(object) = doc.get_object(id) else { continue };
+                if is_page_node(object) {
+                    continue;
+                }
+                assigned.insert(id);
+                document_part.push(id);
+                let mut children = Vec::new();
+                collect_references(object, &mut children);
+                pending.extend(children.into_iter().rev());
+            }
+        }
+
+        let first_page_part: Vec<ObjectId> = page_sets[0].clone();
+        assigned.extend(first_page_part.iter().copied());
+
+        let mut page_parts = Vec::with_capacity(pages.len() - 1);
+        let mut page_shared = Vec::with_capacity(pages.len() - 1);
+        let mut shared_part = Vec::new();
+        let mut shared_seen = HashSet::new();
+
+        for set in &page_sets[1..] {
+            let mut private = Vec::new();
+            let mut shared = Vec::new();
+            for &id in set {
+                if usage[&id] > 1 {
+                    shared.push(id);
+                    if !assigned.contains(&id) && shared_seen.insert(id) {
+                        shared_part.push(id);
+                    }
+                } else if assigned.insert(id) {
+                    private.push(id);
+                }
+            }
+            page_parts.push(private);
+            page_shared.push(shared);
+        }
+        assigned.extend(shared_part.iter().copied());
+
+        let other_part: Vec<ObjectId> = doc.objects.keys()
+            .filter(|id| !assigned.contains(id))
+            .copied()
+            .collect();
+
+        // Main section objects are numbered from 1 in file order; the
+        // linearization dictionary and first page section follow
+        let mut numbers = HashMap::new();
+        let mut next = 1u32;
+        for &id in page_parts.iter().flatten().chain(&shared_part).chain(&other_part) {
+            numbers.insert(id, next);
+            next += 1;
+        }
+        next += 1;
+        for &id in document_part.iter().chain(&first_page_part) {
+            numbers.insert(id, next);
+            next += 1;
+        }
+
+        Ok(Self {
+            document_part,
+            first_page_part,
+            page_parts,
+            shared_part,
+            other_part,
+            page_shared,
+            numbers,
+        })
+    }
+
+    /// Objects in the main cross-reference section
+    fn main_count(&self) -> u32 {
+        (self.page_parts.iter().map(Vec::len).sum::<usize>() + self.shared_part.len() + self.other_part.len()) as u32
+    }
+
+    fn linearization_number(&self) -> u32 {
+        self.main_count() + 1
+    }
+
+    fn hint_number(&self) -> u32 {
+        self.linearization_number() + (self.document_part.len() + self.first_page_part.len()) as u32 + 1
+    }
+
+    fn write(&self, doc: &Document) -> Vec<u8> {
+        let page_count = self.page_parts.len() as u32 + 1;
+        let first_page_number = self.numbers[&self.first_page_part[0]];
+        let lin_number = self.linearization_number();
+        let hint_number = self.hint_number();
+        let first_section_count = hint_number - lin_number + 1;
+
+        let serialize = |id: &ObjectId| -> Vec<u8> {
+            let number = self.numbers[id];
+            let object = doc.objects.get(id).map(|o| self.renumber(o)).unwrap_or(Object::Null);
+            let mut out = format!("{} 0 obj\n", number).into_bytes();
+            writer::write_object(&mut out, &object);
+            out.extend_from_slice(b"\nendobj\n");
+            out
+        };
+
+        let document_bytes: Vec<Vec<u8>> = self.document_part.iter().map(&serialize).collect();
+        let first_page_bytes: Vec<Vec<u8>> = self.first_page_part.iter().map(&serialize).collect();
+        let page_bytes: Vec<Vec<Vec<u8>>> = self.page_parts.iter()
+            .map(|part| part.iter().map(&serialize).collect())
+            .collect();
+        let shared_bytes: Vec<Vec<u8>> = self.shared_part.iter().map(&serialize).collect();
+        let other_bytes: Vec<Vec<u8>> = self.other_part.iter().map(&serialize).collect();
+
+        let trailer_tail = self.trailer_tail(doc);
+        let mut header = format!("%PDF-{}\n", doc.version).into_bytes();
+        header.extend_from_slice(b"%\xE2\xE3\xCF\xD3\n");
+
+        // Offsets are first laid out without the hint stream, as the hint
+        // tables themselves require (ISO 32000-1 F.4)
+        let values = Prefix::default();
+        let prefix_len = header.len()
+            + values.linearization_dict(lin_number, first_page_number, page_count).len()
+            + first_xref_len(lin_number, first_section_count)
+            + values.first_trailer(hint_number + 1, &trailer_tail).len();
+
+        let mut cursor = prefix_len;
+        let mut offsets: HashMap<u32, usize> = HashMap::new();
+        let mut place = |id: &ObjectId, bytes: &Vec<u8>, cursor: &mut usize| {
+            offsets.insert(self.numbers[id], *cursor);
+            *cursor += bytes.len();
+        };
+
+        for (id, bytes) in self.document_part.iter().zip(&document_bytes) {
+            place(id, bytes, &mut cursor);
+        }
+        let hint_offset = cursor;
+        let first_page_start = cursor;
+        for (id, bytes) in self.first_page_part.iter().zip(&first_page_bytes) {
+            place(id, bytes, &mut cursor);
+        }
+        let first_page_end = cursor;
+        let mut page_ranges = vec![(first_page_start, first_page_end)];
+        for (part, bytes) in self.page_parts.iter().zip(&page_bytes) {
+            let start = cursor;
+            for (id, bytes) in part.iter().zip(bytes) {
+                place(id, bytes, &mut cursor);
+            }
+            page_ranges.push((start, cursor));
+        }
+        let shared_start = cursor;
+        for (id, bytes) in self.shared_part.iter().zip(&shared_bytes) {
+            place(id, bytes, &mut cursor);
+        }
+        for (id, bytes) in self.other_part.iter().zip(&other_bytes) {
+            place(id, bytes, &mut cursor);
+        }
+
+        let hint_data = self.hint_data(
+            &page_ranges,
+            &first_page_bytes,
+            &shared_bytes,
+            shared_start,
+        );
+        let hint_stream = Stream::new(dictionary! { "S" => hint_data.shared_offset as i64 }, hint_data.bytes);
+        let mut hint_bytes = format!("{} 0 obj\n", hint_number).into_bytes();
+        writer::write_object(&mut hint_bytes, &Object::Stream(hint_stream));
+        hint_bytes.extend_from_slice(b"\nendobj\n");
+
+        // Everything after the hint stream moves down by its length
+        let shift = hint_bytes.len();
+        for offset in offsets.values_mut() {
+            if *offset >= hint_offset {
+                *offset += shift;
+            }
+        }
+        offsets.insert(hint_number, hint_offset);
+
+        let main_xref_offset = cursor + shift;
+        let main_xref_head = format!("xref\n0 {}", lin_number);
+        let mut main_tail = main_xref_head.clone().into_bytes();
+        main_tail.push(b'\n');
+        main_tail.extend_from_slice(b"0000000000 65535 f \n");
+        for number in 1..lin_number {
+            main_tail.extend_from_slice(format!("{:010} 00000 n \n", offsets[&number]).as_bytes());
+        }
+        main_tail.extend_from_slice(format!("trailer\n<</Size {}>>\n", lin_number).as_bytes());
+
+        let values = Prefix {
+            file_length: 0,
+            hint_offset,
+            hint_length: shift,
+            first_page_end: first_page_end + shift,
+            main_xref_entries: main_xref_offset + main_xref_head.len(),
+            main_xref_offset,
+        };
+        let lin_dict = values.linearization_dict(lin_number, first_page_number, page_count);
+        let first_xref_offset = header.len() + lin_dict.len();
+        main_tail.extend_from_slice(format!("startxref\n{}\n%%EOF\n", first_xref_offset).as_bytes());
+
+        let file_length = main_xref_offset + main_tail.len();
+        let values = Prefix { file_length, ..values };
+
+        offsets.insert(lin_number, header.len());
+        let mut out = Vec::with_capacity(file_length);
+        out.extend_from_slice(&header);
+        out.extend_from_slice(&values.linearization_dict(lin_number, first_page_number, page_count));
+        out.extend_from_slice(format!("xref\n{} {}\n", lin_number, first_section_count).as_bytes());
+        for number in lin_number..=hint_number {
+            out.extend_from_slice(format!("{:010} 00000 n \n", offsets[&number]).as_bytes());
+        }
+        out.extend_from_slice(&values.first_trailer(hint_number + 1, &trailer_tail));
+
+        for bytes in &document_bytes {
+            out.extend_from_slice(bytes);
+        }
+        out.extend_from_slice(&hint_bytes);
+        for bytes in first_page_bytes.iter().chain(page_bytes.iter().flatten()).chain(&shared_bytes).chain(&other_bytes) {
+            out.extend_from_slice(bytes);
+        }
+        out.extend_from_slice(&main_tail);
+
+        out
+    }
+
+    /// Copy of `object` with references rewritten to the new numbering
+    fn renumber(&self, object: &Object) -> Object {
+        match object {
+            Object::Reference(id) => match self.numbers.get(id) {
+                Some(&number) => Object::Reference((number, 0)),
+                None => Object::Null,
+            },
+            Object::Array(items) => Object::Array(items.iter().map(|item| self.renumber(item)).collect()),
+            Object::Dictionary(dict) => Object::Dictionary(self.renumber_dictionary(dict)),
+            Object::Stream(stream) => {
+                let mut copy = stream.clone();
+                copy.dict = self.renumber_dictionary(&stream.dict);
+                Object::Stream(copy)
+            }
+            other => other.clone(),
+        }
+    }
+
+    fn renumber_dictionary(&self, dict: &Dictionary) -> Dictionary {
+        let mut copy = Dictionary::new();
+        for (key, value) in dict.iter() {
+            copy.set(key.clone(), self.renumber(value));
+        }
+        copy
+    }
+
+    /// Root, Info and ID entries shared by the first page trailer
+    fn trailer_tail(&self, doc: &Document) -> Vec<u8> {
+        let mut tail = Dictionary::new();
+        for key in [&b"Root"[..], b"Info", b"ID"] {
+            if let Ok(value) = doc.trailer.get(key) {
+                let value = self.renumber(value);
+                if !matches!(value, Object::Null) {
+                    tail.set(key, value);
+                }
+            }
+        }
+        let mut out = Vec::new();
+        writer::write_object(&mut out, &Object::Dictionary(tail));
+        // Strip the surrounding << >> so the entries can be spliced in
+        out[2..out.len() - 2].to_vec()
+    }
+
+    /// Page offset and shared object hint tables (ISO 32000-1 F.4.1, F.4.2)
+    fn hint_data(
+        &self,
+        page_ranges: &[(usize, usize)],
+        first_page_bytes: &[Vec<u8>],
+        shared_bytes: &[Vec<u8>],
+        shared_start: usize,
+    ) -> HintData {
+        let first_page_index: HashMap<ObjectId, usize> = self.first_page_part.iter()
+            .enumerate()
+            .map(|(i, &id)| (id, i))
+            .collect();
+        let shared_index: HashMap<ObjectId, usize> = self.shared_part.iter()
+            .enumerate()
+            .map(|(i, &id)| (id, first_page_bytes.len() + i))
+            .collect();
+
+        let object_counts: Vec<u64> = std::iter::once(self.first_page_part.len())
+            .chain(self.page_parts.iter().map(Vec::len))
+            .map(|n| n as u64)
+            .collect();
+        let lengths: Vec<u64> = page_ranges.iter().map(|&(start, end)| (end - start) as u64).collect();
+        let shared_refs: Vec<Vec<u64>> = std::iter::once(Vec::new())
+            .chain(self.page_shared.iter().map(|ids| {
+                ids.iter()
+                    .filter_map(|id| first_page_index.get(id).or_else(|| shared_index.get(id)))
+                    .map(|&i| i as u64)
+                    .collect()
+            }))
+            .collect();
+
+        let min_objects = object_counts.iter().copied().min().unwrap_or(0);
+        let max_objects = object_counts.iter().copied().max().unwrap_or(0);
+        let min_length = lengths.iter().copied().min().unwrap_or(0);
+        let max_length = lengths.iter().copied().max().unwrap_or(0);
+        let max_refs = shared_refs.iter().map(|refs| refs.len() as u64).max().unwrap_or(0);
+        let max_id = shared_refs.iter().flatten().copied().max().unwrap_or(0);
+
+        let objects_bits = bits_needed(max_objects - min_objects);
+        let length_bits = bits_needed(max_length - min_length);
+        let refs_bits = bits_needed(max_refs);
+        let id_bits = bits_needed(max_id);
+
+        let mut bits = BitWriter::default();
+
+        // Page offset hint table header
+        bits.write(min_objects, 32);
+        bits.write(page_ranges[0].0 as u64, 32);
+        bits.write(objects_bits as u64, 16);
+        bits.write(min_length, 32);
+        bits.write(length_bits as u64, 16);
+        bits.write(0, 32); // Content streams are treated as starting at the page object
+        bits.write(0, 16);
+        bits.write(min_length, 32);
+        bits.write(length_bits as u64, 16);
+        bits.write(refs_bits as u64, 16);
+        bits.write(id_bits as u64, 16);
+        bits.write(0, 16);
+        bits.write(1, 16);
+
+        // Per-page entries, one item at a time across all pages
+        for &count in &object_counts {
+            bits.write(count - min_objects, objects_bits);
+        }
+        bits.align();
+        for &length in &lengths {
+            bits.write(length - min_length, length_bits);
+        }
+        bits.align();
+        for refs in &shared_refs {
+            bits.write(refs.len() as u64, refs_bits);
+        }
+        bits.align();
+        for &id in shared_refs.iter().flatten() {
+            bits.write(id, id_bits);
+        }
+        bits.align();
+        // Fractional positions use zero bits; content offsets are all zero
+        for &length in &lengths {
+            bits.write(length - min_length, length_bits);
+        }
+        bits.align();
+
+        let shared_offset = bits.bytes.len();
+
+        // Shared object hint table
+        let group_lengths: Vec<u64> = first_page_bytes.iter()
+            .chain(shared_bytes)
+            .map(|bytes| bytes.len() as u64)
+            .collect();
+        let min_group = group_lengths.iter().copied().min().unwrap_or(0);
+        let max_group = group_lengths.iter().copied().max().unwrap_or(0);
+        let group_bits = bits_needed(max_group - min_group);
+
+        let (first_shared_number, first_shared_offset) = match self.shared_part.first() {
+            Some(id) => (self.numbers[id] as u64, shared_start as u64),
+            None => (0, 0),
+        };
+        bits.write(first_shared_number, 32);
+        bits.write(first_shared_offset, 32);
+        bits.write(first_page_bytes.len() as u64, 32);
+        bits.write(group_lengths.len() as u64, 32);
+        bits.write(0, 16); // Every group holds a single object
+        bits.write(min_group, 32);
+        bits.write(group_bits as u64, 16);
+
+        for &length in &group_lengths {
+            bits.write(length - min_group, group_bits);
+        }
+        bits.align();
+        for _ in &group_lengths {
+            bits.write(0, 1); // No MD5 signatures
+        }
+        bits.align();
+
+        HintData {
+            bytes: bits.bytes,
+            shared_offset,
+        }
+    }
+}
+
+struct HintData {
+    bytes: Vec<u8>,
+    shared_offset: usize,
+}
+
+/// Values that are only known once the whole file is laid out; they are
+/// written at a fixed width so the prefix length never changes
+#[derive(Default, Clone, Copy)]
+struct Prefix {
+    file_length: usize,
+    hint_offset: usize,
+    hint_length: usize,
+    first_page_end: usize,
+    main_xref_entries: usize,
+    main_xref_offset: usize,
+}
+
+impl Prefix {
+    fn linearization_dict(&self, number: u32, first_page: u32, page_count: u32) -> Vec<u8> {
+        format!(
+            "{} 0 obj\n<</Linearized 1/L {:010}/H [{:010} {:010}]/O {}/E {:010}/N {}/T {:010}>>\nendobj\n",
+            number,
+            self.file_length,
+            self.hint_offset,
+            self.hint_length,
+            first_page,
+            self.first_page_end,
+            page_count,
+            self.main_xref_entries,
+        ).into_bytes()
+    }
+
+    fn first_trailer(&self, size: u32, tail: &[u8]) -> Vec<u8> {
+        let mut out = format!("trailer\n<</Size {}/Prev {:010}", size, self.main_xref_offset).into_bytes();
+        out.extend_from_slice(tail);
+        out.extend_from_slice(b">>\nstartxref\n0\n%%EOF\n");
+        out
+    }
+}
+
+fn first_xref_len(first: u32, count: u32) -> usize {
+    format!("xref\n{} {}\n", first, count).len() + 20 * count as usize
+}
+
+/// Objects a page needs, starting with the page object itself.
+///
+/// The walk never crosses into other pages or the page tree, so links and
+/// parent pointers do not pull the rest of the document into one page.
+fn page_objects(doc: &Document, page_id: ObjectId, catalog_id: ObjectId) -> Vec<ObjectId> {
+    let mut result = vec![page_id];
+    let mut seen: HashSet<ObjectId> = HashSet::from([page_id, catalog_id]);
+    let mut pending = Vec::new();
+
+    if let Ok(page) = doc.get_dictionary(page_id) {
+        for (key, value) in page.iter() {
+            if key.as_slice() != b"Parent" {
+                collect_references(value, &mut pending);
+            }
+        }
+    }
+    pending.reverse();
+
+    while let Some(id) = pending.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        let Ok(object) = doc.get_object(id) else { continue };
+        if is_page_node(object) {
+            continue;
+        }
+        result.push(id);
+        let mut children = Vec::new();
+        collect_references(object, &mut children);
+        pending.extend(children.into_iter().rev());
+    }
+
+    result
+}
+
+fn is_page_node(object: &Object) -> bool {
+    object.as_dict().is_ok_and(|dict| dict.type_is(b"Page") || dict.type_is(b"Pages"))
+}
+
+/// References held by an object, skipping parent pointers
+fn collect_references(object: &Object, references: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => references.push(*id),
+        Object::Array(items) => {
+            for item in items {
+                collect_references(item, references);
+            }
+        }
+        Object::Dictionary(dict) => collect_dictionary_references(dict, references),
+        Object::Stream(stream) => collect_dictionary_references(&stream.dict, references),
+        _ => {}
+    }
+}
+
+fn collect_dictionary_references(dict: &Dictionary, references: &mut Vec<ObjectId>) {
+    for (key, value) in dict.iter() {
+        if key.as_slice() != b"Parent" {
+            collect_references(value, references);
+        }
+    }
+}
+
+fn bits_needed(value: u64) -> u32 {
+    64 - value.leading_zeros()
+}
+
+/// Writes big-endian bit fields, most significant bit first
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    used: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        for bit in (0..bits).rev() {
+            self.current = (self.current << 1) | ((value >> bit) & 1) as u8;
+            self.used += 1;
+            if self.used == 8 {
+                self.bytes.push(self.current);
+                self.current = 0;
+                self.used = 0;
+            }
+        }
+    }
+
+    /// Pad the current byte with zero bits
+    fn align(&mut self) {
+        if self.used > 0 {
+            self.current <<= 8 - self.used;
+            self.bytes.push(self.current);
+            self.current = 0;
+            self.used = 0;
+        }
+    }
+}
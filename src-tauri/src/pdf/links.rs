@@ -0,0 +1,403 @@
+use crate::commands::{LinkReport, LinkReportResult, OutlineEntry, PdfLink};
+use crate::config::{self, AppConfig};
+use lopdf::{decode_text_string, Dictionary, Document, Object, ObjectId};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name trees deeper than this are treated as malformed
+const MAX_NAME_TREE_DEPTH: usize = 32;
+
+/// Extract link annotations and the outline of each PDF into a report file
+pub async fn extract_links(
+    config: &AppConfig,
+    paths: &[String],
+    format: &str,
+) -> Result<LinkReportResult, String> {
+    if format != "csv" && format != "json" {
+        return Err(format!("Unsupported report format: {}", format));
+    }
+    let output_folder = config::get_output_path(config, "PDF_Reports")?;
+
+    let mut reports = Vec::new();
+    let mut output_files = Vec::new();
+
+    for path in paths {
+        let report = link_report(path)?;
+
+        let stem = Path::new(path).file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        let report_path = output_folder.join(format!("{}_links.{}", stem, format));
+        let contents = if format == "csv" {
+            to_csv(&report)
+        } else {
+            serde_json::to_string_pretty(&report)
+                .map_err(|e| format!("Failed to serialize report: {}", e))?
+        };
+        fs::write(&report_path, contents)
+            .map_err(|e| format!("Failed to write report: {}", e))?;
+
+        output_files.push(report_path.to_string_lossy().to_string());
+        reports.push(report);
+    }
+
+    let links: usize = reports.iter().map(|r| r.links.len()).sum();
+    let broken: u32 = reports.iter().map(|r| r.broken).sum();
+    let message = if broken == 0 {
+        format!("{}件のリンクを抽出しました", links)
+    } else {
+        format!("{}件のリンクを抽出しました（リンク切れ {}件）", links, broken)
+    };
+
+    Ok(LinkReportResult {
+        success: true,
+        output_files,
+        output_folder: output_folder.to_string_lossy().to_string(),
+        message,
+        reports,
+    })
+}
+
+fn link_report(path: &str) -> Result<LinkReport, String> {
+    let doc = super::load_decrypted(path)?;
+    let resolver = Resolver::new(&doc, path);
+
+    let mut links = Vec::new();
+    for (page_number, page_id) in doc.get_pages() {
+        let annotations = match doc.get_page_annotations(page_id) {
+            Ok(annotations) => annotations,
+            Err(_) => continue,
+        };
+
+        for annotation in annotations {
+            if annotation.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Link") {
+                continue;
+            }
+            let Some(target) = resolver.target_of(annotation) else {
+                continue;
+            };
+
+            links.push(PdfLink {
+                page: page_number,
+                kind: target.kind.to_string(),
+                target: target.target,
+                dest_page: target.dest_page,
+                rect: rect(&doc, annotation),
+                broken: target.broken,
+            });
+        }
+    }
+
+    let outline = resolver.outline();
+    let broken = links.iter().filter(|l| l.broken).count()
+        + outline.iter().filter(|o| o.broken).count();
+
+    Ok(LinkReport {
+        path: path.to_string(),
+        links,
+        outline,
+        broken: broken as u32,
+    })
+}
+
+fn rect(doc: &Document, annotation: &Dictionary) -> Option<[f32; 4]> {
+    let values = annotation.get_deref(b"Rect", doc).and_then(Object::as_array).ok()?;
+    match values.iter().map(Object::as_float).collect::<Result<Vec<f32>, _>>() {
+        Ok(values) if values.len() == 4 => Some([values[0], values[1], values[2], values[3]]),
+        _ => None,
+    }
+}
+
+/// Where a link or outline entry goes
+struct Target {
+    kind: &'static str,
+    target: String,
+    dest_page: Option<u32>,
+    broken: bool,
+}
+
+impl Target {
+    fn new(kind: &'static str, target: String) -> Self {
+        Self {
+            kind,
+            target,
+            dest_page: None,
+            broken: false,
+        }
+    }
+}
+
+struct Resolver<'a> {
+    doc: &'a Document,
+    page_numbers: HashMap<ObjectId, u32>,
+    /// Relative file links are resolved against the PDF's folder
+    base_dir: PathBuf,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(doc: &'a Document, path: &str) -> Self {
+        Self {
+            doc,
+            page_numbers: doc.get_pages().into_iter().map(|(number, id)| (id, number)).collect(),
+            base_dir: Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default(),
+        }
+    }
+
+    /// Target of a link annotation or outline item, from its Dest or action
+    fn target_of(&self, dict: &Dictionary) -> Option<Target> {
+        if let Ok(dest) = dict.get(b"Dest") {
+            return Some(self.destination(dest));
+        }
+        let action = dict.get_deref(b"A", self.doc).and_then(Object::as_dict).ok()?;
+        Some(self.action(action))
+    }
+
+    fn action(&self, action: &Dictionary) -> Target {
+        let kind = action.get(b"S").and_then(Object::as_name).unwrap_or(b"");
+        match kind {
+            b"URI" => {
+                let uri = action.get_deref(b"URI", self.doc)
+                    .and_then(Object::as_str)
+                    .map(|uri| String::from_utf8_lossy(uri).trim().to_string())
+                    .unwrap_or_default();
+                let broken = uri.is_empty();
+                Target { broken, ..Target::new("uri", uri) }
+            }
+            b"GoTo" => match action.get(b"D") {
+                Ok(dest) => self.destination(dest),
+                Err(_) => Target { broken: true, ..Target::new("internal", String::new()) },
+            },
+            b"GoToR" | b"Launch" => self.file(action),
+            b"Named" => {
+                let name = action.get(b"N").and_then(Object::as_name).unwrap_or(b"");
+                Target::new("named", String::from_utf8_lossy(name).to_string())
+            }
+            b"JavaScript" => Target::new("javascript", "JavaScript".to_string()),
+            other => Target::new("other", String::from_utf8_lossy(other).to_string()),
+        }
+    }
+
+    /// Link to another file; broken when the file does not exist
+    fn file(&self, action: &Dictionary) -> Target {
+        let spec = action.get_deref(b"F", self.doc).ok();
+        let file = match spec {
+            Some(Object::Dictionary(spec)) => [&b"UF"[..], b"F"].iter()
+                .find_map(|key| spec.get_deref(key, self.doc).ok())
+                .and_then(|name| decode_text_string(name).ok()),
+            Some(name) => decode_text_string(name).ok(),
+            None => None,
+        };
+
+        let Some(file) = file.filter(|file| !file.is_empty()) else {
+            return Target { broken: true, ..Target::new("file", String::new()) };
+        };
+        let broken = !self.base_dir.join(&file).exists();
+        Target { broken, ..Target::new("file", file) }
+    }
+
+    /// Resolve an explicit or named destination to a page of this document
+    fn destination(&self, dest: &Object) -> Target {
+        let dest = match self.doc.dereference(dest) {
+            Ok((_, dest)) => dest,
+            Err(_) => return Target { broken: true, ..Target::new("internal", String::new()) },
+        };
+
+        let (name, explicit) = match dest {
+            Object::Array(_) => (None, Some(dest)),
+            Object::Name(name) => {
+                let name = String::from_utf8_lossy(name).to_string();
+                let explicit = self.named_destination(name.as_bytes(), false);
+                (Some(name), explicit)
+            }
+            Object::String(..) => {
+                let name = decode_text_string(dest).unwrap_or_default();
+                let explicit = dest.as_str().ok()
+                    .and_then(|key| self.named_destination(key, true));
+                (Some(name), explicit)
+            }
+            _ => (None, None),
+        };
+
+        let dest_page = explicit.and_then(|dest| self.destination_page(dest));
+        let target = match (&name, dest_page) {
+            (Some(name), _) => name.clone(),
+            (None, Some(page)) => format!("page {}", page),
+            (None, None) => String::new(),
+        };
+
+        Target {
+            dest_page,
+            broken: dest_page.is_none(),
+            ..Target::new("internal", target)
+        }
+    }
+
+    /// Page number of an explicit destination, [page /XYZ left top zoom] etc.
+    fn destination_page(&self, dest: &Object) -> Option<u32> {
+        let dest = match self.doc.dereference(dest).ok()?.1 {
+            // Named destinations may be wrapped as << /D [...] >>
+            Object::Dictionary(dict) => dict.get_deref(b"D", self.doc).ok()?,
+            other => other,
+        };
+        let page = dest.as_array().ok()?.first()?;
+        self.page_numbers.get(&page.as_reference().ok()?).copied()
+    }
+
+    /// Look up a destination by name: names live in the catalog's Dests
+    /// dictionary (PDF 1.1) or, as strings, in the Dests name tree
+    fn named_destination(&self, name: &[u8], string: bool) -> Option<&'a Object> {
+        let catalog = self.doc.catalog().ok()?;
+        if !string {
+            let dests = catalog.get_deref(b"Dests", self.doc).and_then(Object::as_dict).ok()?;
+            return dests.get(name).ok();
+        }
+
+        let tree = catalog.get_deref(b"Names", self.doc).and_then(Object::as_dict).ok()?
+            .get_deref(b"Dests", self.doc).and_then(Object::as_dict).ok()?;
+        self.name_tree_lookup(tree, name, 0)
+    }
+
+    fn name_tree_lookup(&self, node: &'a Dictionary, key: &[u8], depth: usize) -> Option<&'a Object> {
+        if depth > MAX_NAME_TREE_DEPTH {
+            return None;
+        }
+
+        if let Ok(names) = node.get_deref(b"Names", self.doc).and_then(Object::as_array) {
+            for pair in names.chunks(2) {
+                if pair.len() == 2 && pair[0].as_str().is_ok_and(|name| name == key) {
+                    return Some(&pair[1]);
+                }
+            }
+        }
+
+        let kids = node.get_deref(b"Kids", self.doc).and_then(Object::as_array).ok()?;
+        kids.iter()
+            .filter_map(|kid| self.doc.dereference(kid).ok()?.1.as_dict().ok())
+            .find_map(|kid| self.name_tree_lookup(kid, key, depth + 1))
+    }
+
+    /// Flatten the document outline (bookmarks) in reading order
+    fn outline(&self) -> Vec<OutlineEntry> {
+        let mut entries = Vec::new();
+        let first = self.doc.catalog().ok()
+            .and_then(|catalog| catalog.get_deref(b"Outlines", self.doc).ok())
+            .and_then(|outlines| outlines.as_dict().ok())
+            .and_then(|outlines| outlines.get(b"First").and_then(Object::as_reference).ok());
+        self.outline_items(first, 1, &mut entries, &mut HashSet::new());
+        entries
+    }
+
+    fn outline_items(
+        &self,
+        first: Option<ObjectId>,
+        level: u32,
+        entries: &mut Vec<OutlineEntry>,
+        seen: &mut HashSet<ObjectId>,
+    ) {
+        let mut next = first;
+        // `seen` guards against Next/First chains that loop back on themselves
+        while let Some(id) = next.filter(|&id| seen.insert(id)) {
+            let Ok(item) = self.doc.get_dictionary(id) else {
+                break;
+            };
+
+            let title = item.get_deref(b"Title", self.doc).ok()
+                .and_then(|title| decode_text_string(title).ok())
+                .unwrap_or_default();
+            let target = self.target_of(item);
+            entries.push(OutlineEntry {
+                level,
+                title,
+                kind: target.as_ref().map(|t| t.kind.to_string()),
+                target: target.as_ref().map(|t| t.target.clone()),
+                dest_page: target.as_ref().and_then(|t| t.dest_page),
+                broken: target.is_some_and(|t| t.broken),
+            });
+
+            let child = item.get(b"First").and_then(Object::as_reference).ok();
+            self.outline_items(child, level + 1, entries, seen);
+            next = item.get(b"Next").and_then(Object::as_reference).ok();
+        }
+    }
+}
+
+/// One row per link and outline entry. Starts with a BOM so Excel reads
+/// the file as UTF-8.
+fn to_csv(report: &LinkReport) -> String {
+    let mut csv = String::from("\u{FEFF}source,page,level,title,kind,target,dest_page,broken\r\n");
+    let optional = |value: Option<u32>| value.map(|v| v.to_string()).unwrap_or_default();
+
+    for link in &report.links {
+        let row = [
+            "link".to_string(),
+            link.page.to_string(),
+            String::new(),
+            String::new(),
+            link.kind.clone(),
+            link.target.clone(),
+            optional(link.dest_page),
+            link.broken.to_string(),
+        ];
+        push_csv_row(&mut csv, &row);
+    }
+    for entry in &report.outline {
+        let row = [
+            "outline".to_string(),
+            String::new(),
+            entry.level.to_string(),
+            entry.title.clone(),
+            entry.kind.clone().unwrap_or_default(),
+            entry.target.clone().unwrap_or_default(),
+            optional(entry.dest_page),
+            entry.broken.to_string(),
+        ];
+        push_csv_row(&mut csv, &row);
+    }
+    csv
+}
+
+fn push_csv_row(csv: &mut String, fields: &[String]) {
+    let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    csv.push_str(&fields.join(","));
+    csv.push_str("\r\n");
+}
+
+/// Quote a field as needed. Titles and link targets come from the PDF, so
+/// anything a spreadsheet would run as a formula is prefixed with `'`.
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields_are_quoted() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn csv_formulas_are_neutralized() {
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("\tcmd"), "'\tcmd");
+        assert_eq!(csv_field("\rcmd"), "\"'\rcmd\"");
+        assert_eq!(csv_field("a=b"), "a=b");
+    }
+}
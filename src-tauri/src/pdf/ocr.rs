@@ -0,0 +1,210 @@
+use super::copy::{self, ObjectCopier};
+use super::{overlay, render, text};
+use crate::commands::{ConvertResult, OcrOptions, PdfOutputOptions};
+use crate::config::{self, AppConfig};
+use image::DynamicImage;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const TESSERACT: &str = "tesseract";
+
+/// Resolution pages are rendered at for recognition
+const DEFAULT_DPI: u32 = 300;
+
+/// Higher resolutions gain no accuracy and make Tesseract very slow
+const MAX_DPI: u32 = 600;
+
+/// Used when no languages are requested
+const DEFAULT_LANGUAGES: [&str; 2] = ["jpn", "eng"];
+
+/// Tells apart the working folders of OCR runs in progress
+static RUN: AtomicUsize = AtomicUsize::new(0);
+
+/// Make scanned PDFs searchable: each page is rendered, recognized by a
+/// locally installed Tesseract, and given an invisible text layer.
+///
+/// Pages that already have text are left alone unless `force` is set.
+pub async fn ocr(
+    config: &AppConfig,
+    work_dir: &Path,
+    paths: &[String],
+    options: &OcrOptions,
+    output: &PdfOutputOptions,
+) -> Result<ConvertResult, String> {
+    let languages: Vec<String> = if options.languages.is_empty() {
+        DEFAULT_LANGUAGES.iter().map(|language| language.to_string()).collect()
+    } else {
+        options.languages.clone()
+    };
+    // Tesseract joins languages with '+', so names are limited to what packs use
+    if let Some(language) = languages.iter()
+        .find(|language| language.is_empty() || !language.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+    {
+        return Err(format!("Invalid OCR language: {}", language));
+    }
+    let dpi = options.dpi.unwrap_or(DEFAULT_DPI);
+    if dpi == 0 || dpi > MAX_DPI {
+        return Err(format!("OCR resolution must be between 1 and {} DPI", MAX_DPI));
+    }
+    check_tesseract(&languages)?;
+
+    let output_folder = config::get_output_path(config, "PDF_Operations")?;
+    let run_dir = work_dir.join(format!("{}_{}", std::process::id(), RUN.fetch_add(1, Ordering::Relaxed)));
+    fs::create_dir_all(&run_dir)
+        .map_err(|e| format!("Failed to create OCR work folder: {}", e))?;
+
+    let languages = languages.join("+");
+    let result: Result<Vec<String>, String> = paths.iter()
+        .map(|path| {
+            let output_path = ocr_file(path, &run_dir, &languages, dpi, options, output, &output_folder)?;
+            Ok(output_path.to_string_lossy().to_string())
+        })
+        .collect();
+    let _ = fs::remove_dir_all(&run_dir);
+    let output_files = result?;
+
+    Ok(ConvertResult {
+        success: true,
+        output_files,
+        output_folder: output_folder.to_string_lossy().to_string(),
+        message: format!("{}個のPDFをOCR処理しました", paths.len()),
+    })
+}
+
+/// Fail early, and clearly, when Tesseract or a language pack is missing
+fn check_tesseract(languages: &[String]) -> Result<(), String> {
+    let output = Command::new(TESSERACT)
+        .arg("--list-langs")
+        .output()
+        .map_err(|e| format!("Tesseract not found or failed to execute ({}). Install Tesseract OCR and add it to PATH", e))?;
+
+    // Older versions print the list to stderr
+    let listing = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let installed: HashSet<&str> = listing.lines()
+        .skip_while(|line| !line.starts_with("List of available languages"))
+        .skip(1)
+        .map(str::trim)
+        .collect();
+
+    let missing: Vec<&str> = languages.iter()
+        .map(String::as_str)
+        .filter(|language| !installed.contains(language))
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Tesseract language pack not installed: {}", missing.join(", ")))
+    }
+}
+
+/// Render a page for Tesseract, or None when it has text already and
+/// `force` is off. The PDF is opened for each page, so that PDFium is not
+/// held while Tesseract runs.
+fn render_for_recognition(path: &str, page_number: u32, dpi: u32, force: bool) -> Result<Option<DynamicImage>, String> {
+    let rendered = render::open(path)?;
+    let pdf_page = rendered.pages().get((page_number - 1) as u16)
+        .map_err(|e| format!("Failed to read page: {}", e))?;
+    if !force && !text::page_text(&pdf_page)?.trim().is_empty() {
+        return Ok(None);
+    }
+    render::render_page(&pdf_page, dpi).map(Some)
+}
+
+fn ocr_file(
+    path: &str,
+    run_dir: &Path,
+    languages: &str,
+    dpi: u32,
+    options: &OcrOptions,
+    output: &PdfOutputOptions,
+    output_folder: &Path,
+) -> Result<PathBuf, String> {
+    let mut doc = super::load_decrypted(path)?;
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    let pages = super::select_pages(page_ids.len() as u32, options.pages.as_deref())?;
+
+    for page_number in pages {
+        let Some(image) = render_for_recognition(path, page_number, dpi, options.force)? else {
+            continue;
+        };
+        let layer = recognize(&image, dpi, languages, run_dir)?;
+        let Some(&layer_page) = layer.get_pages().values().next() else {
+            continue;
+        };
+
+        let mut copier = ObjectCopier::new(&layer);
+        let form = overlay::import_form(&mut doc, &layer, &mut copier, layer_page)?;
+        let page_id = page_ids[(page_number - 1) as usize];
+        let page = copy::inherited_page(&doc, page_id)?;
+        let matrix = placement(form.bbox, overlay::page_box(&doc, &page), rotation(&page));
+        overlay::stamp_form(&mut doc, page_id, form.id, matrix, true)?;
+    }
+
+    let stem = Path::new(path).file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    let output_path = output_folder.join(format!("{}_ocr.pdf", stem));
+    super::save_document(&mut doc, &output_path, output)?;
+    Ok(output_path)
+}
+
+/// Run Tesseract on a rendered page, returning its text-only PDF: a single
+/// page of invisible text the size of the image at `dpi`
+fn recognize(image: &DynamicImage, dpi: u32, languages: &str, run_dir: &Path) -> Result<Document, String> {
+    let input = run_dir.join("page.png");
+    let output_base = run_dir.join("page");
+    image.to_luma8().save(&input)
+        .map_err(|e| format!("Failed to write image: {}", e))?;
+
+    let output = Command::new(TESSERACT)
+        .arg(&input)
+        .arg(&output_base)
+        .args(["-l", languages, "--dpi", &dpi.to_string(), "-c", "textonly_pdf=1", "pdf"])
+        .output()
+        .map_err(|e| format!("Tesseract not found or failed to execute: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Tesseract error: {}", stderr.trim()));
+    }
+
+    Document::load(output_base.with_extension("pdf"))
+        .map_err(|e| format!("Failed to read OCR result: {}", e))
+}
+
+/// Page rotation in quarter turns clockwise, as degrees
+fn rotation(page: &Dictionary) -> i64 {
+    let degrees = page.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0);
+    (degrees.rem_euclid(360) + 45) / 90 % 4 * 90
+}
+
+/// Matrix taking the text layer, recognized from the page as displayed,
+/// onto the page's unrotated box
+fn placement(layer: [f32; 4], page: [f32; 4], rotate: i64) -> [f32; 6] {
+    let [x0, y0, x1, y1] = page;
+    let (width, height) = (x1 - x0, y1 - y0);
+
+    // Size of the page as displayed, and the matrix from displayed to page space
+    let (shown_width, shown_height, [a, b, c, d, e, f]) = match rotate {
+        90 => (height, width, [0.0, 1.0, -1.0, 0.0, x1, y0]),
+        180 => (width, height, [-1.0, 0.0, 0.0, -1.0, x1, y1]),
+        270 => (height, width, [0.0, -1.0, 1.0, 0.0, x0, y1]),
+        _ => (width, height, [1.0, 0.0, 0.0, 1.0, x0, y0]),
+    };
+
+    let (layer_width, layer_height) = (layer[2] - layer[0], layer[3] - layer[1]);
+    if layer_width <= 0.0 || layer_height <= 0.0 {
+        return [a, b, c, d, e, f];
+    }
+    let (sx, sy) = (shown_width / layer_width, shown_height / layer_height);
+    let (tx, ty) = (-layer[0] * sx, -layer[1] * sy);
+
+    [sx * a, sx * b, sy * c, sy * d, tx * a + ty * c + e, tx * b + ty * d + f]
+}
@@ -0,0 +1,254 @@
+use super::copy::{self, ObjectCopier};
+use crate::commands::{ConvertResult, OverlayOptions, PdfOutputOptions};
+use crate::config::{self, AppConfig};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// How stationery pages are sized onto target pages
+#[derive(Clone, Copy, PartialEq)]
+enum Scale {
+    /// Uniformly scaled to fit, centred
+    Fit,
+    /// Scaled to cover the page exactly
+    Stretch,
+    /// Actual size, aligned to the top-left corner
+    None,
+}
+
+/// A page copied into another document as a Form XObject
+#[derive(Clone, Copy)]
+pub(super) struct Form {
+    pub(super) id: ObjectId,
+    pub(super) bbox: [f32; 4],
+}
+
+/// Place pages of a stationery PDF (letterhead, background template)
+/// under or over the pages of each target PDF
+pub async fn overlay(
+    config: &AppConfig,
+    paths: &[String],
+    stationery_path: &str,
+    options: &OverlayOptions,
+    output: &PdfOutputOptions,
+) -> Result<ConvertResult, String> {
+    let over = match options.position.as_str() {
+        "under" => false,
+        "over" => true,
+        other => return Err(format!("Unsupported overlay position: {}", other)),
+    };
+    let scale = match options.scale.as_deref().unwrap_or("fit") {
+        "fit" => Scale::Fit,
+        "stretch" => Scale::Stretch,
+        "none" => Scale::None,
+        other => return Err(format!("Unsupported overlay scale: {}", other)),
+    };
+
+    let output_folder = config::get_output_path(config, "PDF_Operations")?;
+    let stationery = super::load_decrypted(stationery_path)?;
+    let stationery_pages: Vec<ObjectId> = stationery.get_pages().into_values().collect();
+    if stationery_pages.is_empty() {
+        return Err("Stationery PDF has no pages".to_string());
+    }
+
+    let mut output_files = Vec::new();
+    for path in paths {
+        let mut doc = super::load_decrypted(path)?;
+        let mut copier = ObjectCopier::new(&stationery);
+        let mut forms: HashMap<ObjectId, Form> = HashMap::new();
+
+        for (index, page_id) in doc.get_pages().into_values().enumerate() {
+            if options.first_page_only && index > 0 {
+                break;
+            }
+
+            let source_page = if options.cycle {
+                stationery_pages[index % stationery_pages.len()]
+            } else {
+                stationery_pages[0]
+            };
+            let form = match forms.get(&source_page) {
+                Some(&form) => form,
+                None => {
+                    let form = import_form(&mut doc, &stationery, &mut copier, source_page)?;
+                    forms.insert(source_page, form);
+                    form
+                }
+            };
+
+            let page = copy::inherited_page(&doc, page_id)?;
+            let matrix = placement(form.bbox, page_box(&doc, &page), scale);
+            stamp_form(&mut doc, page_id, form.id, matrix, over)?;
+        }
+
+        let stem = Path::new(path).file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        let output_path = output_folder.join(format!("{}_overlay.pdf", stem));
+        super::save_document(&mut doc, &output_path, output)?;
+        output_files.push(output_path.to_string_lossy().to_string());
+    }
+
+    Ok(ConvertResult {
+        success: true,
+        output_files,
+        output_folder: output_folder.to_string_lossy().to_string(),
+        message: format!("{}個のPDFに重ね合わせました", paths.len()),
+    })
+}
+
+/// Copy a page of `source` into `target` as a Form XObject
+pub(super) fn import_form(
+    target: &mut Document,
+    source: &Document,
+    copier: &mut ObjectCopier,
+    page_id: ObjectId,
+) -> Result<Form, String> {
+    let page = copy::inherited_page(source, page_id)?;
+    let bbox = page_box(source, &page);
+
+    // Content streams are joined with a newline so tokens never run together
+    let mut content = Vec::new();
+    for stream_id in source.get_page_contents(page_id) {
+        if let Ok(stream) = source.get_object(stream_id).and_then(Object::as_stream) {
+            // Unfiltered streams are plain content already; encoded data that
+            // cannot be decoded would turn into garbage operators
+            if stream.dict.has(b"Filter") {
+                let data = stream.decompressed_content()
+                    .map_err(|e| format!("Failed to decode page content: {}", e))?;
+                content.extend_from_slice(&data);
+            } else {
+                content.extend_from_slice(&stream.content);
+            }
+            content.push(b'\n');
+        }
+    }
+
+    let mut dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Form",
+        "BBox" => bbox.iter().map(|&v| Object::Real(v)).collect::<Vec<_>>(),
+    };
+    // A page's transparency group has to travel with it to blend the same way
+    for key in [&b"Resources"[..], b"Group"] {
+        if let Ok(value) = page.get(key) {
+            dict.set(key, copier.copy(target, value));
+        }
+    }
+
+    let mut stream = Stream::new(dict, content);
+    let _ = stream.compress();
+    Ok(Form {
+        id: target.add_object(stream),
+        bbox,
+    })
+}
+
+/// Draw a form under or over the existing content of a page, placed by `matrix`
+pub(super) fn stamp_form(
+    doc: &mut Document,
+    page_id: ObjectId,
+    form_id: ObjectId,
+    matrix: [f32; 6],
+    over: bool,
+) -> Result<(), String> {
+    let page = copy::inherited_page(doc, page_id)?;
+
+    // The page gets a resource dictionary of its own, so pages that shared
+    // one are not stamped through it
+    let mut resources = resolved_dict(doc, page.get(b"Resources").ok());
+    let mut xobjects = resolved_dict(doc, resources.get(b"XObject").ok());
+    let name = (1..)
+        .map(|i| format!("FxOverlay{}", i))
+        .find(|name| !xobjects.has(name.as_bytes()))
+        .unwrap_or_default();
+    xobjects.set(name.as_bytes(), form_id);
+    resources.set("XObject", xobjects);
+
+    let matrix: Vec<String> = matrix.iter().map(|&v| number(v)).collect();
+    let stamp = format!("q {} cm /{} Do Q\n", matrix.join(" "), name);
+
+    let existing = doc.get_page_contents(page_id).into_iter().map(Object::Reference);
+    let contents: Vec<Object> = if over {
+        // Isolate the page's own graphics state, which it may leave unbalanced
+        let open = doc.add_object(Stream::new(dictionary! {}, b"q\n".to_vec()));
+        let close = doc.add_object(Stream::new(dictionary! {}, format!("Q\n{}", stamp).into_bytes()));
+        std::iter::once(Object::Reference(open))
+            .chain(existing)
+            .chain(std::iter::once(Object::Reference(close)))
+            .collect()
+    } else {
+        let under = doc.add_object(Stream::new(dictionary! {}, stamp.into_bytes()));
+        std::iter::once(Object::Reference(under)).chain(existing).collect()
+    };
+
+    let page = doc.get_dictionary_mut(page_id)
+        .map_err(|e| format!("Failed to read page: {}", e))?;
+    page.set("Resources", resources);
+    page.set("Contents", contents);
+    Ok(())
+}
+
+/// Transformation matrix taking the form's box onto the page's box
+fn placement(form: [f32; 4], page: [f32; 4], scale: Scale) -> [f32; 6] {
+    let (form_width, form_height) = (form[2] - form[0], form[3] - form[1]);
+    let (page_width, page_height) = (page[2] - page[0], page[3] - page[1]);
+    if form_width <= 0.0 || form_height <= 0.0 {
+        return [1.0, 0.0, 0.0, 1.0, page[0] - form[0], page[1] - form[1]];
+    }
+
+    let (sx, sy) = match scale {
+        Scale::Fit => {
+            let s = (page_width / form_width).min(page_height / form_height);
+            (s, s)
+        }
+        Scale::Stretch => (page_width / form_width, page_height / form_height),
+        Scale::None => (1.0, 1.0),
+    };
+
+    let (tx, ty) = match scale {
+        Scale::None => (page[0] - form[0], page[3] - form[3]),
+        _ => (
+            page[0] + (page_width - form_width * sx) / 2.0 - form[0] * sx,
+            page[1] + (page_height - form_height * sy) / 2.0 - form[1] * sy,
+        ),
+    };
+
+    [sx, 0.0, 0.0, sy, tx, ty]
+}
+
+/// Visible area of a page: its CropBox, or MediaBox when there is none
+pub(super) fn page_box(doc: &Document, page: &Dictionary) -> [f32; 4] {
+    [&b"CropBox"[..], b"MediaBox"].iter()
+        .find_map(|key| {
+            let values = page.get_deref(key, doc).and_then(Object::as_array).ok()?;
+            let values: Vec<f32> = values.iter()
+                .map(|value| doc.dereference(value).and_then(|(_, value)| value.as_float()))
+                .collect::<Result<_, _>>()
+                .ok()?;
+            match values[..] {
+                [x1, y1, x2, y2] => Some([x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)]),
+                _ => None,
+            }
+        })
+        .unwrap_or([0.0, 0.0, 612.0, 792.0])
+}
+
+/// Copy of a dictionary that may be stored inline or behind a reference
+fn resolved_dict(doc: &Document, value: Option<&Object>) -> Dictionary {
+    value
+        .and_then(|value| doc.dereference(value).ok())
+        .and_then(|(_, value)| value.as_dict().ok())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Format a number for a content stream, without needless digits
+fn number(value: f32) -> String {
+    let rounded = (value * 10000.0).round() / 10000.0;
+    if rounded == 0.0 {
+        "0".to_string()
+    } else {
+        rounded.to_string()
+    }
+}
@@ -0,0 +1,958 @@
+use super::{fonts, writer};
+use crate::commands::{ConvertResult, PdfaReport, PdfaViolation};
+use crate::config::{self, AppConfig};
+use lopdf::{dictionary, decode_text_string, text_string, Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Action types ISO 19005-2 6.5.1 does not permit
+const FORBIDDEN_ACTIONS: [&[u8]; 11] = [
+    b"Launch", b"Sound", b"Movie", b"ResetForm", b"ImportData", b"Hide",
+    b"SetOCGState", b"Rendition", b"Trans", b"GoTo3DView", b"JavaScript",
+];
+
+/// Annotation types ISO 19005-2 6.3.1 does not permit
+const FORBIDDEN_ANNOTATIONS: [&[u8]; 5] = [b"Sound", b"Movie", b"Screen", b"3D", b"RichMedia"];
+
+const ANNOT_INVISIBLE: i64 = 1;
+const ANNOT_HIDDEN: i64 = 2;
+const ANNOT_PRINT: i64 = 4;
+const ANNOT_NO_VIEW: i64 = 32;
+const ANNOT_TOGGLE_NO_VIEW: i64 = 256;
+const ANNOT_HIDDEN_FLAGS: i64 = ANNOT_INVISIBLE | ANNOT_HIDDEN | ANNOT_NO_VIEW | ANNOT_TOGGLE_NO_VIEW;
+
+/// Limit on name tree nesting, against cyclic or malformed trees
+const MAX_NAME_TREE_DEPTH: usize = 32;
+
+/// Check a PDF against the PDF/A-2b requirements
+pub fn check_pdfa(path: &str) -> Result<PdfaReport, String> {
+    let doc = super::load_document(path)?;
+    let violations = check(&doc);
+
+    Ok(PdfaReport {
+        path: path.to_string(),
+        conformant: violations.is_empty(),
+        violations,
+    })
+}
+
+/// Convert PDFs to PDF/A-2b on a best-effort basis
+pub async fn convert_to_pdfa(
+    config: &AppConfig,
+    paths: &[String],
+) -> Result<ConvertResult, String> {
+    let output_folder = config::get_output_path(config, "PDF_Operations")?;
+    let mut output_files = Vec::new();
+    let mut unresolved = 0;
+    let mut removed_attachments = Vec::new();
+
+    for path in paths {
+        let mut doc = super::load_decrypted(path)?;
+        removed_attachments.extend(make_conformant(&mut doc));
+        unresolved += check(&doc).len();
+
+        let stem = Path::new(path).file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        let output_path = output_folder.join(format!("{}_pdfa.pdf", stem));
+        writer::save(&doc, &output_path)?;
+
+        output_files.push(output_path.to_string_lossy().to_string());
+    }
+
+    let mut message = if unresolved == 0 {
+        "PDF/A-2bに変換しました".to_string()
+    } else {
+        format!("PDF/A-2bに変換しました（未解決の問題: {}件）", unresolved)
+    };
+    if !removed_attachments.is_empty() {
+        message.push_str(&format!("。添付ファイルを削除しました: {}", removed_attachments.join(", ")));
+    }
+
+    Ok(ConvertResult {
+        success: true,
+        output_files,
+        output_folder: output_folder.to_string_lossy().to_string(),
+        message,
+    })
+}
+
+// ============================================
+// Checks
+// ============================================
+
+fn check(doc: &Document) -> Vec<PdfaViolation> {
+    let mut violations = Vec::new();
+
+    if doc.version.parse::<f32>().map(|v| v > 1.7).unwrap_or(true) {
+        violation(&mut violations, "version", format!("PDF version {} is newer than 1.7", doc.version), None);
+    }
+
+    if doc.trailer.has(b"Encrypt") {
+        violation(&mut violations, "encryption", "Document is encrypted".to_string(), None);
+    }
+
+    if !doc.trailer.has(b"ID") {
+        violation(&mut violations, "file-id", "Trailer has no file identifier".to_string(), None);
+    }
+
+    check_metadata(doc, &mut violations);
+
+    let has_output_intent = has_pdfa_output_intent(doc);
+    if !has_output_intent {
+        violation(&mut violations, "output-intent", "No PDF/A output intent (GTS_PDFA1)".to_string(), None);
+    }
+
+    check_fonts(doc, &mut violations);
+
+    if !has_output_intent {
+        for page in transparency_pages(doc) {
+            violation(
+                &mut violations,
+                "transparency",
+                "Transparency used without an output intent or group color space".to_string(),
+                Some(page),
+            );
+        }
+    }
+
+    check_streams(doc, &mut violations);
+    check_actions(doc, &mut violations);
+    check_annotations(doc, &mut violations);
+    check_catalog(doc, &mut violations);
+
+    violations
+}
+
+fn violation(violations: &mut Vec<PdfaViolation>, rule: &str, message: String, page: Option<u32>) {
+    violations.push(PdfaViolation {
+        rule: rule.to_string(),
+        message,
+        page,
+    });
+}
+
+fn check_metadata(doc: &Document, violations: &mut Vec<PdfaViolation>) {
+    let stream = doc.catalog().ok()
+        .and_then(|catalog| catalog.get_deref(b"Metadata", doc).ok())
+        .and_then(|object| object.as_stream().ok());
+
+    let stream = match stream {
+        Some(stream) => stream,
+        None => {
+            violation(violations, "metadata", "XMP metadata stream is missing".to_string(), None);
+            return;
+        }
+    };
+
+    if stream.dict.has(b"Filter") {
+        violation(violations, "metadata", "XMP metadata stream must not be filtered".to_string(), None);
+    }
+
+    let content = stream.get_plain_content().unwrap_or_else(|_| stream.content.clone());
+    let xmp = String::from_utf8_lossy(&content);
+    let part = xmp_value(&xmp, "pdfaid:part");
+    let conformance = xmp_value(&xmp, "pdfaid:conformance").map(|c| c.to_uppercase());
+
+    let identified = part.as_deref() == Some("2")
+        && matches!(conformance.as_deref(), Some("A") | Some("B") | Some("U"));
+    if !identified {
+        violation(
+            violations,
+            "metadata",
+            "XMP metadata does not identify the file as PDF/A-2b".to_string(),
+            None,
+        );
+    }
+}
+
+/// Read a simple XMP property in either attribute or element form
+fn xmp_value(xmp: &str, property: &str) -> Option<String> {
+    let start = xmp.find(property)? + property.len();
+    let value: String = xmp[start..]
+        .trim_start_matches(|c: char| c == '=' || c == '"' || c == '\'' || c == '>' || c.is_whitespace())
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect();
+
+    if value.is_empty() { None } else { Some(value) }
+}
+
+fn has_pdfa_output_intent(doc: &Document) -> bool {
+    let intents = doc.catalog().ok()
+        .and_then(|catalog| catalog.get_deref(b"OutputIntents", doc).ok())
+        .and_then(|object| object.as_array().ok());
+
+    intents.is_some_and(|intents| {
+        intents.iter().any(|intent| {
+            doc.dereference(intent).ok()
+                .and_then(|(_, object)| object.as_dict().ok())
+                .is_some_and(|dict| {
+                    dict.get(b"S").and_then(Object::as_name).ok() == Some(b"GTS_PDFA1")
+                        && dict.has(b"DestOutputProfile")
+                })
+        })
+    })
+}
+
+fn check_fonts(doc: &Document, violations: &mut Vec<PdfaViolation>) {
+    let usage = font_usage(doc);
+
+    for (id, object) in &doc.objects {
+        let dict = match object.as_dict() {
+            Ok(dict) if dict.type_is(b"Font") => dict,
+            _ => continue,
+        };
+
+        let subtype = dict.get(b"Subtype").and_then(Object::as_name).unwrap_or(b"");
+        // Composite fonts are checked through their descendants, Type 3 glyphs are content streams
+        if subtype == b"Type0" || subtype == b"Type3" {
+            continue;
+        }
+
+        if !fonts::is_embedded(doc, dict) {
+            let name = dict.get(b"BaseFont")
+                .and_then(Object::as_name_str)
+                .unwrap_or("unknown");
+            violation(
+                violations,
+                "fonts",
+                format!("Font {} is not embedded", name),
+                usage.get(id).copied(),
+            );
+        }
+    }
+}
+
+/// Map font objects (including CID descendants) to the first page using them
+fn font_usage(doc: &Document) -> HashMap<ObjectId, u32> {
+    let mut usage = HashMap::new();
+
+    for (page_number, page_id) in doc.get_pages() {
+        let fonts = match doc.get_page_resources(page_id) {
+            Ok((resources, resource_ids)) => {
+                let mut dicts: Vec<&Dictionary> = resource_ids.iter()
+                    .filter_map(|id| doc.get_dictionary(*id).ok())
+                    .collect();
+                dicts.extend(resources);
+                dicts
+            }
+            Err(_) => continue,
+        };
+
+        for resources in fonts {
+            let font_dict = match resources.get_deref(b"Font", doc).and_then(Object::as_dict) {
+                Ok(dict) => dict,
+                Err(_) => continue,
+            };
+            for (_, font_ref) in font_dict.iter() {
+                if let Ok(font_id) = font_ref.as_reference() {
+                    usage.entry(font_id).or_insert(page_number);
+                    let descendants = doc.get_dictionary(font_id)
+                        .and_then(|font| font.get_deref(b"DescendantFonts", doc))
+                        .and_then(Object::as_array);
+                    if let Ok(descendants) = descendants {
+                        for descendant in descendants {
+                            if let Ok(id) = descendant.as_reference() {
+                                usage.entry(id).or_insert(page_number);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    usage
+}
+
+/// Pages that use transparency without declaring a group color space
+fn transparency_pages(doc: &Document) -> Vec<u32> {
+    if !doc.objects.values().any(is_transparent) {
+        return Vec::new();
+    }
+
+    let mut pages = Vec::new();
+    for (page_number, page_id) in doc.get_pages() {
+        let page = match doc.get_dictionary(page_id) {
+            Ok(page) => page,
+            Err(_) => continue,
+        };
+
+        let has_group_cs = page.get_deref(b"Group", doc)
+            .and_then(Object::as_dict)
+            .is_ok_and(|group| group.has(b"CS"));
+        if has_group_cs {
+            continue;
+        }
+
+        if uses_transparency(doc, page, &mut HashSet::new()) {
+            pages.push(page_number);
+        }
+    }
+
+    pages
+}
+
+fn is_transparent(object: &Object) -> bool {
+    match object {
+        Object::Dictionary(dict) => is_transparent_dict(dict),
+        Object::Stream(stream) => is_transparent_dict(&stream.dict),
+        _ => false,
+    }
+}
+
+fn is_transparent_dict(dict: &Dictionary) -> bool {
+    if let Ok(smask) = dict.get(b"SMask") {
+        if smask.as_name().ok() != Some(b"None") {
+            return true;
+        }
+    }
+
+    for key in [&b"CA"[..], b"ca"] {
+        if dict.get(key).and_then(Object::as_float).is_ok_and(|alpha| alpha < 1.0) {
+            return true;
+        }
+    }
+
+    let group_type = dict.get(b"Group")
+        .and_then(Object::as_dict)
+        .and_then(|group| group.get(b"S"))
+        .and_then(Object::as_name);
+    matches!(group_type, Ok(b"Transparency"))
+}
+
+/// Walk everything reachable from a page's dictionary, without following
+/// links back up the tree or across to other pages
+fn uses_transparency(doc: &Document, dict: &Dictionary, seen: &mut HashSet<ObjectId>) -> bool {
+    if is_transparent_dict(dict) {
+        return true;
+    }
+
+    dict.iter()
+        .filter(|(key, _)| !matches!(key.as_slice(), b"Parent" | b"P" | b"Dest" | b"Next" | b"Prev"))
+        .any(|(_, value)| object_uses_transparency(doc, value, seen))
+}
+
+fn object_uses_transparency(doc: &Document, object: &Object, seen: &mut HashSet<ObjectId>) -> bool {
+    match object {
+        Object::Reference(id) => {
+            if !seen.insert(*id) {
+                return false;
+            }
+            match doc.get_object(*id) {
+                Ok(target) => object_uses_transparency(doc, target, seen),
+                Err(_) => false,
+            }
+        }
+        Object::Array(items) => items.iter().any(|item| object_uses_transparency(doc, item, seen)),
+        Object::Dictionary(dict) => uses_transparency(doc, dict, seen),
+        Object::Stream(stream) => uses_transparency(doc, &stream.dict, seen),
+        _ => false,
+    }
+}
+
+fn check_streams(doc: &Document, violations: &mut Vec<PdfaViolation>) {
+    let mut lzw = false;
+    let mut external = false;
+
+    for object in doc.objects.values() {
+        if let Object::Stream(stream) = object {
+            if stream.filters().is_ok_and(|filters| filters.iter().any(|f| f == "LZWDecode")) {
+                lzw = true;
+            }
+            if stream.dict.has(b"F") || stream.dict.has(b"FFilter") || stream.dict.has(b"FDecodeParms") {
+                external = true;
+            }
+        }
+    }
+
+    if lzw {
+        violation(violations, "lzw", "LZW compressed streams are not permitted".to_string(), None);
+    }
+    if external {
+        violation(violations, "external-stream", "Streams reference external files".to_string(), None);
+    }
+}
+
+fn check_actions(doc: &Document, violations: &mut Vec<PdfaViolation>) {
+    let mut forbidden = HashSet::new();
+    let mut additional_actions = false;
+
+    for object in doc.objects.values() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &stream.dict,
+            _ => continue,
+        };
+        if let Some(action) = forbidden_action(dict) {
+            forbidden.insert(action);
+        }
+        for key in [&b"A"[..], b"OpenAction"] {
+            if let Ok(Object::Dictionary(action)) = dict.get(key) {
+                if let Some(action) = forbidden_action(action) {
+                    forbidden.insert(action);
+                }
+            }
+        }
+        if dict.has(b"AA") {
+            additional_actions = true;
+        }
+    }
+
+    let has_javascript = names_tree(doc).is_some_and(|names| names.has(b"JavaScript"));
+    if has_javascript {
+        forbidden.insert("JavaScript".to_string());
+    }
+
+    let mut forbidden: Vec<String> = forbidden.into_iter().collect();
+    forbidden.sort();
+    for action in forbidden {
+        violation(violations, "actions", format!("{} actions are not permitted", action), None);
+    }
+    if additional_actions {
+        violation(violations, "actions", "Additional actions (AA) are not permitted".to_string(), None);
+    }
+}
+
+fn forbidden_action(dict: &Dictionary) -> Option<String> {
+    let action = dict.get(b"S").and_then(Object::as_name).ok()?;
+    if FORBIDDEN_ACTIONS.contains(&action) {
+        Some(String::from_utf8_lossy(action).to_string())
+    } else {
+        None
+    }
+}
+
+fn check_annotations(doc: &Document, violations: &mut Vec<PdfaViolation>) {
+    for (page_number, page_id) in doc.get_pages() {
+        let annotations = match doc.get_page_annotations(page_id) {
+            Ok(annotations) => annotations,
+            Err(_) => continue,
+        };
+
+        for annotation in annotations {
+            let subtype = annotation.get(b"Subtype").and_then(Object::as_name).unwrap_or(b"");
+            if FORBIDDEN_ANNOTATIONS.contains(&subtype) {
+                violation(
+                    violations,
+                    "annotations",
+                    format!("{} annotations are not permitted", String::from_utf8_lossy(subtype)),
+                    Some(page_number),
+                );
+                continue;
+            }
+            if subtype == b"Popup" {
+                continue;
+            }
+
+            let flags = annotation.get(b"F").and_then(Object::as_i64).unwrap_or(0);
+            if flags & ANNOT_PRINT == 0 || flags & ANNOT_HIDDEN_FLAGS != 0 {
+                violation(
+                    violations,
+                    "annotations",
+                    format!("{} annotation is not set to print visibly", String::from_utf8_lossy(subtype)),
+                    Some(page_number),
+                );
+            }
+        }
+    }
+}
+
+fn check_catalog(doc: &Document, violations: &mut Vec<PdfaViolation>) {
+    if let Some(form) = acro_form(doc) {
+        if form.get(b"NeedAppearances").and_then(Object::as_bool).unwrap_or(false) {
+            violation(violations, "forms", "NeedAppearances must not be true".to_string(), None);
+        }
+        if form.has(b"XFA") {
+            violation(violations, "forms", "XFA forms are not permitted".to_string(), None);
+        }
+    }
+
+    if names_tree(doc).is_some_and(|names| names.has(b"EmbeddedFiles")) {
+        violation(
+            violations,
+            "embedded-files",
+            "Embedded files cannot be verified as PDF/A".to_string(),
+            None,
+        );
+    }
+}
+
+fn names_tree(doc: &Document) -> Option<&Dictionary> {
+    doc.catalog().ok()?
+        .get_deref(b"Names", doc).ok()?
+        .as_dict().ok()
+}
+
+fn acro_form(doc: &Document) -> Option<&Dictionary> {
+    doc.catalog().ok()?
+        .get_deref(b"AcroForm", doc).ok()?
+        .as_dict().ok()
+}
+
+// ============================================
+// Conversion
+// ============================================
+
+/// Returns the names of the attached files removed, which cannot be kept
+/// since they are not known to be PDF/A themselves
+fn make_conformant(doc: &mut Document) -> Vec<String> {
+    if doc.version.parse::<f32>().map(|v| v > 1.7).unwrap_or(true) {
+        doc.version = "1.7".to_string();
+    }
+
+    replace_lzw_streams(doc);
+    let removed_attachments = attachment_names(doc);
+    remove_forbidden_actions(doc);
+    fix_annotations(doc);
+    fix_catalog(doc);
+
+    if !has_pdfa_output_intent(doc) {
+        add_output_intent(doc);
+    }
+
+    writer::ensure_file_id(doc);
+    write_metadata(doc);
+    writer::prune_unreachable(doc);
+    removed_attachments
+}
+
+fn replace_lzw_streams(doc: &mut Document) {
+    for object in doc.objects.values_mut() {
+        if let Object::Stream(stream) = object {
+            let uses_lzw = stream.filters()
+                .is_ok_and(|filters| filters.iter().any(|f| f == "LZWDecode"));
+            if uses_lzw {
+                if let Ok(content) = stream.decompressed_content() {
+                    stream.set_plain_content(content);
+                    let _ = stream.compress();
+                }
+            }
+        }
+    }
+}
+
+fn remove_forbidden_actions(doc: &mut Document) {
+    let forbidden_ids: HashSet<ObjectId> = doc.objects.iter()
+        .filter(|(_, object)| object.as_dict().ok().and_then(forbidden_action).is_some())
+        .map(|(id, _)| *id)
+        .collect();
+
+    for object in doc.objects.values_mut() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => continue,
+        };
+
+        dict.remove(b"AA");
+        for key in [&b"A"[..], b"OpenAction", b"Next"] {
+            let remove = match dict.get(key) {
+                Ok(Object::Reference(id)) => forbidden_ids.contains(id),
+                Ok(Object::Dictionary(action)) => forbidden_action(action).is_some(),
+                _ => false,
+            };
+            if remove {
+                dict.remove(key);
+            }
+        }
+    }
+
+    if let Some(names_id) = names_tree_id(doc) {
+        if let Ok(names) = doc.get_dictionary_mut(names_id) {
+            names.remove(b"JavaScript");
+            names.remove(b"EmbeddedFiles");
+        }
+    } else if let Ok(catalog) = doc.catalog_mut() {
+        if let Ok(names) = catalog.get_mut(b"Names").and_then(Object::as_dict_mut) {
+            names.remove(b"JavaScript");
+            names.remove(b"EmbeddedFiles");
+        }
+    }
+}
+
+/// Names of the files in the EmbeddedFiles name tree, as their file
+/// specifications give them
+fn attachment_names(doc: &Document) -> Vec<String> {
+    let mut names = Vec::new();
+    let tree = names_tree(doc)
+        .and_then(|names| names.get_deref(b"EmbeddedFiles", doc).and_then(Object::as_dict).ok());
+    if let Some(tree) = tree {
+        collect_attachment_names(doc, tree, &mut names, 0);
+    }
+    names
+}
+
+fn collect_attachment_names(doc: &Document, node: &Dictionary, names: &mut Vec<String>, depth: usize) {
+    if depth > MAX_NAME_TREE_DEPTH {
+        return;
+    }
+
+    if let Ok(pairs) = node.get_deref(b"Names", doc).and_then(Object::as_array) {
+        for pair in pairs.chunks(2) {
+            let spec = pair.get(1)
+                .and_then(|spec| doc.dereference(spec).ok())
+                .and_then(|(_, spec)| spec.as_dict().ok());
+            let name = spec
+                .and_then(|spec| spec.get(b"UF").or_else(|_| spec.get(b"F")).ok())
+                .or(pair.first())
+                .and_then(|name| decode_text_string(name).ok());
+            names.push(name.unwrap_or_default());
+        }
+    }
+
+    if let Ok(kids) = node.get_deref(b"Kids", doc).and_then(Object::as_array) {
+        for kid in kids {
+            if let Some(kid) = doc.dereference(kid).ok().and_then(|(_, kid)| kid.as_dict().ok()) {
+                collect_attachment_names(doc, kid, names, depth + 1);
+            }
+        }
+    }
+}
+
+fn names_tree_id(doc: &Document) -> Option<ObjectId> {
+    doc.catalog().ok()?.get(b"Names").ok()?.as_reference().ok()
+}
+
+fn fix_annotations(doc: &mut Document) {
+    let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+
+    for page_id in pages {
+        let annots = doc.get_dictionary(page_id).ok()
+            .and_then(|page| page.get(b"Annots").ok())
+            .cloned();
+
+        let (annots_id, mut entries) = match annots {
+            Some(Object::Reference(id)) => match doc.get_object(id).and_then(Object::as_array) {
+                Ok(entries) => (Some(id), entries.clone()),
+                Err(_) => continue,
+            },
+            Some(Object::Array(entries)) => (None, entries),
+            _ => continue,
+        };
+
+        entries.retain(|entry| {
+            let annotation = doc.dereference(entry).ok().and_then(|(_, object)| object.as_dict().ok());
+            let subtype = annotation.and_then(|a| a.get(b"Subtype").and_then(Object::as_name).ok());
+            !subtype.is_some_and(|subtype| FORBIDDEN_ANNOTATIONS.contains(&subtype))
+        });
+
+        for entry in entries.iter_mut() {
+            let annotation = match entry {
+                Object::Reference(id) => match doc.get_dictionary_mut(*id) {
+                    Ok(annotation) => annotation,
+                    Err(_) => continue,
+                },
+                Object::Dictionary(annotation) => annotation,
+                _ => continue,
+            };
+            if annotation.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Popup") {
+                continue;
+            }
+            let flags = annotation.get(b"F").and_then(Object::as_i64).unwrap_or(0);
+            annotation.set("F", (flags | ANNOT_PRINT) & !ANNOT_HIDDEN_FLAGS);
+        }
+
+        match annots_id {
+            Some(id) => doc.set_object(id, entries),
+            None => {
+                if let Ok(page) = doc.get_dictionary_mut(page_id) {
+                    page.set("Annots", entries);
+                }
+            }
+        }
+    }
+}
+
+fn fix_catalog(doc: &mut Document) {
+    let form_id = doc.catalog().ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| form.as_reference().ok());
+
+    let form = match form_id {
+        Some(id) => doc.get_dictionary_mut(id).ok(),
+        None => doc.catalog_mut().ok()
+            .and_then(|catalog| catalog.get_mut(b"AcroForm").ok())
+            .and_then(|form| form.as_dict_mut().ok()),
+    };
+
+    if let Some(form) = form {
+        form.remove(b"NeedAppearances");
+        form.remove(b"XFA");
+    }
+}
+
+fn add_output_intent(doc: &mut Document) {
+    let mut profile = Stream::new(dictionary! { "N" => 3 }, srgb_icc_profile());
+    let _ = profile.compress();
+    let profile_id = doc.add_object(profile);
+
+    let intent_id = doc.add_object(dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFA1",
+        "OutputConditionIdentifier" => Object::string_literal("sRGB IEC61966-2.1"),
+        "RegistryName" => Object::string_literal("http://www.color.org"),
+        "Info" => Object::string_literal("sRGB IEC61966-2.1"),
+        "DestOutputProfile" => profile_id,
+    });
+
+    if let Ok(catalog) = doc.catalog_mut() {
+        catalog.set("OutputIntents", vec![Object::Reference(intent_id)]);
+    }
+}
+
+// ============================================
+// XMP metadata
+// ============================================
+
+/// Info dictionary keys mirrored into XMP, as PDF/A requires them to agree
+const INFO_KEYS: [&str; 8] = [
+    "Title", "Author", "Subject", "Keywords", "Creator", "Producer", "CreationDate", "ModDate",
+];
+
+fn write_metadata(doc: &mut Document) {
+    let now = chrono::Local::now();
+    let offset = now.format("%z").to_string();
+    let pdf_now = format!("D:{}{}'{}'", now.format("%Y%m%d%H%M%S"), &offset[..3], &offset[3..]);
+
+    let info_id = match doc.trailer.get(b"Info").and_then(Object::as_reference) {
+        Ok(id) => id,
+        Err(_) => {
+            let id = doc.add_object(Dictionary::new());
+            doc.trailer.set("Info", id);
+            id
+        }
+    };
+
+    let mut values = HashMap::new();
+    if let Ok(info) = doc.get_dictionary_mut(info_id) {
+        info.set("ModDate", Object::string_literal(pdf_now.clone()));
+        if !info.has(b"CreationDate") {
+            info.set("CreationDate", Object::string_literal(pdf_now));
+        }
+        if !info.has(b"Producer") {
+            info.set("Producer", text_string("FluxForge"));
+        }
+        for key in INFO_KEYS {
+            if let Ok(value) = info.get(key.as_bytes()).and_then(decode_text_string) {
+                values.insert(key, value);
+            }
+        }
+    }
+
+    let xmp = build_xmp(&values);
+    let mut stream = Stream::new(
+        dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+        xmp.into_bytes(),
+    );
+    stream.allows_compression = false;
+    let metadata_id = doc.add_object(stream);
+
+    if let Ok(catalog) = doc.catalog_mut() {
+        catalog.set("Metadata", metadata_id);
+    }
+}
+
+fn build_xmp(values: &HashMap<&str, String>) -> String {
+    let mut properties = String::new();
+    let text = |key: &str| values.get(key).map(|value| xml_escape(value));
+
+    if let Some(title) = text("Title") {
+        properties.push_str(&format!(
+            "      <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n",
+            title
+        ));
+    }
+    if let Some(author) = text("Author") {
+        properties.push_str(&format!(
+            "      <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n",
+            author
+        ));
+    }
+    if let Some(subject) = text("Subject") {
+        properties.push_str(&format!(
+            "      <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+            subject
+        ));
+    }
+    if let Some(keywords) = text("Keywords") {
+        properties.push_str(&format!("      <pdf:Keywords>{}</pdf:Keywords>\n", keywords));
+    }
+    if let Some(producer) = text("Producer") {
+        properties.push_str(&format!("      <pdf:Producer>{}</pdf:Producer>\n", producer));
+    }
+    if let Some(creator) = text("Creator") {
+        properties.push_str(&format!("      <xmp:CreatorTool>{}</xmp:CreatorTool>\n", creator));
+    }
+    if let Some(created) = values.get("CreationDate").and_then(|d| pdf_date_to_xmp(d)) {
+        properties.push_str(&format!("      <xmp:CreateDate>{}</xmp:CreateDate>\n", created));
+    }
+    if let Some(modified) = values.get("ModDate").and_then(|d| pdf_date_to_xmp(d)) {
+        properties.push_str(&format!("      <xmp:ModifyDate>{}</xmp:ModifyDate>\n", modified));
+        properties.push_str(&format!("      <xmp:MetadataDate>{}</xmp:MetadataDate>\n", modified));
+    }
+
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+            "  <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+            "    <rdf:Description rdf:about=\"\"\n",
+            "        xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n",
+            "        xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"\n",
+            "        xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n",
+            "        xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\">\n",
+            "      <pdfaid:part>2</pdfaid:part>\n",
+            "      <pdfaid:conformance>B</pdfaid:conformance>\n",
+            "{}",
+            "    </rdf:Description>\n",
+            "  </rdf:RDF>\n",
+            "</x:xmpmeta>\n",
+            "<?xpacket end=\"w\"?>",
+        ),
+        properties
+    )
+}
+
+/// Convert `D:YYYYMMDDHHmmSS+HH'mm'` to ISO 8601
+pub(super) fn pdf_date_to_xmp(date: &str) -> Option<String> {
+    let digits = date.trim_start_matches("D:");
+    let year = digits.get(..4).filter(|s| s.chars().all(|c| c.is_ascii_digit()))?;
+
+    let field = |start: usize, default: &'static str| -> String {
+        digits.get(start..start + 2)
+            .filter(|s| s.chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or(default)
+            .to_string()
+    };
+
+    let mut iso = format!(
+        "{}-{}-{}T{}:{}:{}",
+        year, field(4, "01"), field(6, "01"), field(8, "00"), field(10, "00"), field(12, "00")
+    );
+
+    let zone = digits.get(14..).unwrap_or("");
+    match zone.chars().next() {
+        Some('Z') => iso.push('Z'),
+        Some(sign @ ('+' | '-')) => {
+            let zone_digits: String = zone[1..].chars().filter(|c| c.is_ascii_digit()).collect();
+            if zone_digits.len() >= 4 {
+                iso.push_str(&format!("{}{}:{}", sign, &zone_digits[..2], &zone_digits[2..4]));
+            } else if zone_digits.len() >= 2 {
+                iso.push_str(&format!("{}{}:00", sign, &zone_digits[..2]));
+            }
+        }
+        _ => {}
+    }
+
+    Some(iso)
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// ============================================
+// sRGB ICC profile
+// ============================================
+
+/// Build an ICC v2 display profile for sRGB (D50-adapted primaries)
+fn srgb_icc_profile() -> Vec<u8> {
+    let mut curve = Vec::with_capacity(12 + 1024 * 2);
+    curve.extend_from_slice(b"curv\0\0\0\0");
+    curve.extend_from_slice(&1024u32.to_be_bytes());
+    for i in 0..1024 {
+        let v = i as f64 / 1023.0;
+        let linear = if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) };
+        curve.extend_from_slice(&((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+
+    let tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"desc", icc_description("sRGB IEC61966-2.1")),
+        (b"cprt", icc_text("No copyright, use freely")),
+        (b"wtpt", icc_xyz(0.9642, 1.0, 0.8249)),
+        (b"rXYZ", icc_xyz(0.4361, 0.2225, 0.0139)),
+        (b"gXYZ", icc_xyz(0.3851, 0.7169, 0.0971)),
+        (b"bXYZ", icc_xyz(0.1431, 0.0606, 0.7141)),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    let mut table = Vec::new();
+    let mut data = Vec::new();
+    let data_start = 128 + 4 + 12 * tags.len();
+    for (signature, body) in &tags {
+        table.extend_from_slice(*signature);
+        table.extend_from_slice(&((data_start + data.len()) as u32).to_be_bytes());
+        table.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        data.extend_from_slice(body);
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+    }
+
+    let size = data_start + data.len();
+    let mut profile = Vec::with_capacity(size);
+    profile.extend_from_slice(&(size as u32).to_be_bytes());
+    profile.extend_from_slice(&[0; 4]); // CMM
+    profile.extend_from_slice(&[2, 0x10, 0, 0]); // Version 2.1
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    profile.extend_from_slice(&[0x07, 0xCE, 0, 2, 0, 9, 0, 6, 0, 0x31, 0, 0]); // 1998-02-09 06:49:00
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 24]); // Platform, flags, manufacturer, model, attributes
+    profile.extend_from_slice(&[0; 4]); // Perceptual intent
+    profile.extend_from_slice(&icc_xyz(0.9642, 1.0, 0.8249)[8..]);
+    profile.extend_from_slice(&[0; 48]); // Creator, profile ID, reserved
+    profile.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
+
+fn icc_xyz(x: f64, y: f64, z: f64) -> Vec<u8> {
+    let mut tag = b"XYZ \0\0\0\0".to_vec();
+    for value in [x, y, z] {
+        tag.extend_from_slice(&((value * 65536.0).round() as i32).to_be_bytes());
+    }
+    tag
+}
+
+fn icc_text(text: &str) -> Vec<u8> {
+    let mut tag = b"text\0\0\0\0".to_vec();
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+    tag
+}
+
+fn icc_description(text: &str) -> Vec<u8> {
+    let mut tag = b"desc\0\0\0\0".to_vec();
+    tag.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+    tag.extend_from_slice(&[0; 8]); // Unicode language and count
+    tag.extend_from_slice(&[0; 3]); // ScriptCode code and count
+    tag.extend_from_slice(&[0; 67]);
+    tag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pdf_dates_become_iso_8601() {
+        assert_eq!(pdf_date_to_xmp("D:20240315134530+09'00'").as_deref(), Some("2024-03-15T13:45:30+09:00"));
+        assert_eq!(pdf_date_to_xmp("D:20240315134530-05").as_deref(), Some("2024-03-15T13:45:30-05:00"));
+        assert_eq!(pdf_date_to_xmp("D:20240315134530Z").as_deref(), Some("2024-03-15T13:45:30Z"));
+        assert_eq!(pdf_date_to_xmp("D:2024").as_deref(), Some("2024-01-01T00:00:00"));
+        assert_eq!(pdf_date_to_xmp("20240315").as_deref(), Some("2024-03-15T00:00:00"));
+        assert_eq!(pdf_date_to_xmp("D:2024031").as_deref(), Some("2024-03-01T00:00:00"));
+        assert_eq!(pdf_date_to_xmp("yesterday"), None);
+    }
+}
@@ -0,0 +1,339 @@
+use crate::commands::ConvertOptions;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, GrayImage, ImageFormat};
+use std::io::{Cursor, Seek, Write};
+use tiff::encoder::{colortype, compression::DeflateLevel, Compression, DirectoryEncoder, TiffEncoder, TiffKindStandard};
+use tiff::tags::{CompressionMethod, PhotometricInterpretation, ResolutionUnit, Tag};
+
+/// Quality used for JPEG and lossy WebP when none is given
+const DEFAULT_QUALITY: u8 = 85;
+
+/// Luma below which a pixel becomes black in bilevel output
+const BILEVEL_THRESHOLD: u8 = 128;
+
+/// Widest row the fax encoder can code, which counts pixels in 16 bits
+const MAX_GROUP4_WIDTH: u32 = u16::MAX as u32;
+
+/// How rendered pages are reduced before encoding
+#[derive(Clone, Copy, PartialEq)]
+enum ColorMode {
+    Rgb,
+    Gray,
+    /// 1-bit black and white
+    Mono,
+}
+
+fn color_mode(options: &ConvertOptions) -> Result<ColorMode, String> {
+    // Group 4 can only store black and white
+    if extension(&options.format)? == "tiff" && options.tiff_compression.as_deref() == Some("g4") {
+        return match options.color_mode.as_deref() {
+            None | Some("mono") => Ok(ColorMode::Mono),
+            Some(other) => Err(format!("Group 4 TIFF compression needs color mode \"mono\", not \"{}\"", other)),
+        };
+    }
+
+    match options.color_mode.as_deref().unwrap_or("rgb") {
+        "rgb" => Ok(ColorMode::Rgb),
+        "gray" => Ok(ColorMode::Gray),
+        "mono" => Ok(ColorMode::Mono),
+        other => Err(format!("Unsupported color mode: {}", other)),
+    }
+}
+
+/// File extension for a raster output format
+pub fn extension(format: &str) -> Result<&'static str, String> {
+    match format {
+        "jpg" | "jpeg" => Ok("jpg"),
+        "png" => Ok("png"),
+        "webp" => Ok("webp"),
+        "tiff" | "tif" => Ok("tiff"),
+        _ => Err(format!("Unsupported image format: {}", format)),
+    }
+}
+
+/// Whether pages keep a transparent background. Only PNG and WebP can
+/// store one, and 1-bit output has no room for it.
+pub fn keeps_alpha(options: &ConvertOptions) -> Result<bool, String> {
+    Ok(options.transparent
+        && matches!(extension(&options.format)?, "png" | "webp")
+        && color_mode(options)? != ColorMode::Mono)
+}
+
+/// Apply the colour mode to a rendered page
+pub fn prepare(image: DynamicImage, options: &ConvertOptions) -> Result<DynamicImage, String> {
+    let alpha = keeps_alpha(options)?;
+    Ok(match color_mode(options)? {
+        ColorMode::Rgb if alpha => DynamicImage::ImageRgba8(image.to_rgba8()),
+        ColorMode::Rgb => DynamicImage::ImageRgb8(image.to_rgb8()),
+        ColorMode::Gray if alpha => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+        ColorMode::Gray => DynamicImage::ImageLuma8(image.to_luma8()),
+        ColorMode::Mono => DynamicImage::ImageLuma8(bilevel(image.to_luma8(), options)),
+    })
+}
+
+/// Reduce a grayscale page to pure black (0) and white (255)
+fn bilevel(mut image: GrayImage, options: &ConvertOptions) -> GrayImage {
+    let threshold = options.threshold.unwrap_or(BILEVEL_THRESHOLD) as i32;
+
+    if !options.dither {
+        for pixel in image.pixels_mut() {
+            pixel[0] = if (pixel[0] as i32) < threshold { 0 } else { 255 };
+        }
+        return image;
+    }
+
+    // Floyd-Steinberg; errors are kept in sixteenths, offset by one column
+    // so the left neighbour of the first pixel has somewhere to go
+    let width = image.width() as usize;
+    let mut current = vec![0i32; width + 2];
+    let mut next = vec![0i32; width + 2];
+
+    for row in image.rows_mut() {
+        for (x, pixel) in row.enumerate() {
+            let value = pixel[0] as i32 + current[x + 1] / 16;
+            let out = if value < threshold { 0 } else { 255 };
+            pixel[0] = out as u8;
+
+            let error = value - out;
+            current[x + 2] += error * 7;
+            next[x] += error * 3;
+            next[x + 1] += error * 5;
+            next[x + 2] += error;
+        }
+        std::mem::swap(&mut current, &mut next);
+        next.fill(0);
+    }
+
+    image
+}
+
+/// Encode a single page that has been through `prepare`
+pub fn encode(image: &DynamicImage, dpi: u32, options: &ConvertOptions) -> Result<Vec<u8>, String> {
+    match extension(&options.format)? {
+        "jpg" => {
+            let quality = options.quality.unwrap_or(DEFAULT_QUALITY).clamp(1, 100);
+            let mut data = Vec::new();
+            let mut encoder = JpegEncoder::new_with_quality(&mut data, quality);
+            // Pass concrete buffers: a DynamicImage always encodes as three components
+            match image {
+                DynamicImage::ImageLuma8(gray) => encoder.encode_image(gray),
+                other => encoder.encode_image(&other.to_rgb8()),
+            }
+            .map_err(|e| format!("Failed to encode JPEG: {}", e))?;
+            Ok(data)
+        }
+        "png" if color_mode(options)? == ColorMode::Mono => encode_bilevel_png(&image.to_luma8()),
+        "png" => {
+            let mut data = Vec::new();
+            image.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+                .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+            Ok(data)
+        }
+        "webp" => encode_webp(image, options),
+        _ => {
+            let mut data = Vec::new();
+            TiffWriter::new(Cursor::new(&mut data), options)?
+                .add_page(image, dpi)?;
+            Ok(data)
+        }
+    }
+}
+
+/// Write black and white pages as 1-bit PNG, which the image crate cannot do
+fn encode_bilevel_png(image: &GrayImage) -> Result<Vec<u8>, String> {
+    let (width, height) = image.dimensions();
+    let stride = width.div_ceil(8) as usize;
+    let mut packed = vec![0u8; stride * height as usize];
+    for (y, row) in image.rows().enumerate() {
+        for (x, pixel) in row.enumerate() {
+            if pixel[0] >= BILEVEL_THRESHOLD {
+                packed[y * stride + x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::One);
+    encoder.write_header()
+        .and_then(|mut writer| {
+            writer.write_image_data(&packed)?;
+            writer.finish()
+        })
+        .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    Ok(data)
+}
+
+fn encode_webp(image: &DynamicImage, options: &ConvertOptions) -> Result<Vec<u8>, String> {
+    // libwebp takes 8-bit RGB or RGBA only
+    let image = match image {
+        DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => image.clone(),
+        other if other.color().has_alpha() => DynamicImage::ImageRgba8(other.to_rgba8()),
+        other => DynamicImage::ImageRgb8(other.to_rgb8()),
+    };
+
+    let encoder = webp::Encoder::from_image(&image)
+        .map_err(|e| format!("Failed to encode WebP: {}", e))?;
+    let data = if options.lossless {
+        encoder.encode_lossless()
+    } else {
+        encoder.encode(options.quality.unwrap_or(DEFAULT_QUALITY).clamp(1, 100) as f32)
+    };
+
+    Ok(data.to_vec())
+}
+
+/// TIFF compression schemes offered for PDF rendering
+#[derive(Clone, Copy, PartialEq)]
+enum TiffCompression {
+    None,
+    Lzw,
+    Deflate,
+    /// CCITT Group 4; pages are reduced to black and white, and those too
+    /// wide for it are stored with LZW
+    Group4,
+}
+
+/// Writes one or more pages into a TIFF file
+pub struct TiffWriter<W: Write + Seek> {
+    encoder: TiffEncoder<W>,
+    compression: TiffCompression,
+}
+
+impl<W: Write + Seek> TiffWriter<W> {
+    pub fn new(writer: W, options: &ConvertOptions) -> Result<Self, String> {
+        let default = if color_mode(options)? == ColorMode::Mono { "g4" } else { "lzw" };
+        let compression = match options.tiff_compression.as_deref().unwrap_or(default) {
+            "none" => TiffCompression::None,
+            "lzw" => TiffCompression::Lzw,
+            "deflate" => TiffCompression::Deflate,
+            "g4" => TiffCompression::Group4,
+            other => return Err(format!("Unsupported TIFF compression: {}", other)),
+        };
+
+        let encoder = TiffEncoder::new(writer)
+            .map_err(|e| format!("Failed to encode TIFF: {}", e))?
+            .with_compression(match compression {
+                TiffCompression::Lzw | TiffCompression::Group4 => Compression::Lzw,
+                TiffCompression::Deflate => Compression::Deflate(DeflateLevel::Balanced),
+                _ => Compression::Uncompressed,
+            });
+
+        Ok(Self { encoder, compression })
+    }
+
+    /// Append a page as a new image directory
+    pub fn add_page(&mut self, image: &DynamicImage, dpi: u32) -> Result<(), String> {
+        self.write_page(image, dpi)
+            .map_err(|e| format!("Failed to encode TIFF: {}", e))
+    }
+
+    fn write_page(&mut self, image: &DynamicImage, dpi: u32) -> tiff::TiffResult<()> {
+        let (width, height) = (image.width(), image.height());
+        let resolution = tiff::encoder::Rational { n: dpi.max(1), d: 1 };
+
+        if self.compression == TiffCompression::Group4 && width <= MAX_GROUP4_WIDTH {
+            write_group4(&mut self.encoder, &image.to_luma8(), resolution)
+        } else if image.color().has_color() {
+            let mut page = self.encoder.new_image::<colortype::RGB8>(width, height)?;
+            page.resolution(ResolutionUnit::Inch, resolution);
+            page.write_data(image.to_rgb8().as_raw())
+        } else {
+            let mut page = self.encoder.new_image::<colortype::Gray8>(width, height)?;
+            page.resolution(ResolutionUnit::Inch, resolution);
+            page.write_data(image.to_luma8().as_raw())
+        }
+    }
+}
+
+/// Write a bilevel page as a single CCITT Group 4 strip.
+///
+/// The tiff crate has no fax encoder, so the directory is built by hand.
+fn write_group4<W: Write + Seek>(
+    encoder: &mut TiffEncoder<W>,
+    image: &GrayImage,
+    resolution: tiff::encoder::Rational,
+) -> tiff::TiffResult<()> {
+    let (width, height) = image.dimensions();
+    let data = group4_strip(image);
+
+    let mut directory: DirectoryEncoder<'_, W, TiffKindStandard> = encoder.image_directory()?;
+    let offset = directory.write_data(data.as_slice())?;
+
+    directory.write_tag(Tag::ImageWidth, width)?;
+    directory.write_tag(Tag::ImageLength, height)?;
+    directory.write_tag(Tag::BitsPerSample, 1u16)?;
+    directory.write_tag(Tag::Compression, CompressionMethod::Fax4)?;
+    directory.write_tag(Tag::PhotometricInterpretation, PhotometricInterpretation::WhiteIsZero)?;
+    directory.write_tag(Tag::StripOffsets, offset as u32)?;
+    directory.write_tag(Tag::SamplesPerPixel, 1u16)?;
+    directory.write_tag(Tag::RowsPerStrip, height)?;
+    directory.write_tag(Tag::StripByteCounts, data.len() as u32)?;
+    directory.write_tag(Tag::XResolution, resolution.clone())?;
+    directory.write_tag(Tag::YResolution, resolution)?;
+    directory.write_tag(Tag::ResolutionUnit, ResolutionUnit::Inch)?;
+    directory.finish()
+}
+
+/// `image` is at most `MAX_GROUP4_WIDTH` wide
+fn group4_strip(image: &GrayImage) -> Vec<u8> {
+    let width = image.width() as u16;
+    let mut encoder = fax::encoder::Encoder::new(fax::VecWriter::new());
+
+    for row in image.rows() {
+        let pels = row.map(|pixel| {
+            if pixel[0] < BILEVEL_THRESHOLD { fax::Color::Black } else { fax::Color::White }
+        });
+        // Writing to a Vec cannot fail
+        let _ = encoder.encode_line(pels, width);
+    }
+
+    match encoder.finish() {
+        Ok(writer) => writer.finish(),
+        Err(never) => match never {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn options(threshold: Option<u8>, dither: bool) -> ConvertOptions {
+        serde_json::from_value(serde_json::json!({
+            "format": "png",
+            "dpi": 150,
+            "color_mode": "mono",
+            "threshold": threshold,
+            "dither": dither,
+        }))
+        .unwrap()
+    }
+
+    fn gray_ramp() -> GrayImage {
+        GrayImage::from_fn(256, 1, |x, _| Luma([x as u8]))
+    }
+
+    #[test]
+    fn threshold_splits_at_the_given_luma() {
+        let image = bilevel(gray_ramp(), &options(None, false));
+        assert_eq!(image.get_pixel(127, 0)[0], 0);
+        assert_eq!(image.get_pixel(128, 0)[0], 255);
+
+        let image = bilevel(gray_ramp(), &options(Some(200), false));
+        assert_eq!(image.get_pixel(199, 0)[0], 0);
+        assert_eq!(image.get_pixel(200, 0)[0], 255);
+    }
+
+    #[test]
+    fn dithering_keeps_the_average_tone() {
+        let image = GrayImage::from_pixel(64, 64, Luma([64]));
+        let image = bilevel(image, &options(None, true));
+        assert!(image.pixels().all(|pixel| pixel[0] == 0 || pixel[0] == 255));
+
+        let white = image.pixels().filter(|pixel| pixel[0] == 255).count() as f32;
+        let share = white / (64.0 * 64.0);
+        assert!((share - 0.25).abs() < 0.02, "{} of the pixels are white", share);
+    }
+}
@@ -0,0 +1,245 @@
+use super::raster;
+use crate::commands::ConvertOptions;
+use image::DynamicImage;
+use pdfium_render::prelude::*;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread::{self, ThreadId};
+
+/// Exported pages are scaled down to stay within this many pixels
+/// (about 400 MB as RGBA), so a poster at 600 DPI cannot exhaust memory
+const MAX_EXPORT_PIXELS: f32 = 100_000_000.0;
+
+static PDFIUM: OnceLock<Pdfium> = OnceLock::new();
+static PDFIUM_INIT: Mutex<()> = Mutex::new(());
+
+/// The thread holding PDFium, and how many holds it has
+static PDFIUM_HOLDER: Mutex<Option<(ThreadId, usize)>> = Mutex::new(None);
+static PDFIUM_RELEASED: Condvar = Condvar::new();
+
+/// A hold on PDFium for the current thread.
+///
+/// PDFium is not thread-safe, so only one thread uses it at a time. Holds
+/// nest, letting a thread open several documents at once; the guard cannot
+/// leave the thread that took it.
+struct PdfiumLock {
+    _thread_bound: PhantomData<*const ()>,
+}
+
+impl PdfiumLock {
+    fn acquire() -> Self {
+        let current = thread::current().id();
+        let mut holder = PDFIUM_HOLDER.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            match &mut *holder {
+                None => {
+                    *holder = Some((current, 1));
+                    break;
+                }
+                Some((thread, holds)) if *thread == current => {
+                    *holds += 1;
+                    break;
+                }
+                Some(_) => holder = PDFIUM_RELEASED.wait(holder).unwrap_or_else(|e| e.into_inner()),
+            }
+        }
+        Self { _thread_bound: PhantomData }
+    }
+}
+
+impl Drop for PdfiumLock {
+    fn drop(&mut self) {
+        let mut holder = PDFIUM_HOLDER.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, holds)) = &mut *holder {
+            *holds -= 1;
+            if *holds == 0 {
+                *holder = None;
+                PDFIUM_RELEASED.notify_all();
+            }
+        }
+    }
+}
+
+/// An open PDF. It holds PDFium for its thread until dropped, so its pages
+/// can be rendered and read without racing other threads.
+pub struct Document {
+    // Declared first so the document closes before PDFium is released
+    document: PdfDocument<'static>,
+    _lock: PdfiumLock,
+}
+
+impl Deref for Document {
+    type Target = PdfDocument<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.document
+    }
+}
+
+/// Shared PDFium instance, bound once per process.
+///
+/// The library is looked up next to the executable first (where the
+/// installer puts it), then on the system library path. Only called with
+/// PDFium held.
+fn pdfium() -> Result<&'static Pdfium, String> {
+    if let Some(pdfium) = PDFIUM.get() {
+        return Ok(pdfium);
+    }
+
+    let _guard = PDFIUM_INIT.lock()
+        .map_err(|_| "PDFium initialization failed".to_string())?;
+    if let Some(pdfium) = PDFIUM.get() {
+        return Ok(pdfium);
+    }
+
+    let bundled = std::env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .and_then(|dir| Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path(&dir)).ok());
+
+    let bindings = match bundled {
+        Some(bindings) => bindings,
+        None => Pdfium::bind_to_system_library().map_err(|e| {
+            format!(
+                "PDFium library not found ({}). Place {} next to FluxForge or install it system-wide",
+                e,
+                Pdfium::pdfium_platform_library_name().to_string_lossy()
+            )
+        })?,
+    };
+
+    Ok(PDFIUM.get_or_init(|| Pdfium::new(bindings)))
+}
+
+/// Open a PDF for rendering or text extraction, waiting while another
+/// thread has one open
+pub fn open(path: &str) -> Result<Document, String> {
+    if !Path::new(path).exists() {
+        return Err(format!("File not found: {}", path));
+    }
+
+    let lock = PdfiumLock::acquire();
+    let document = pdfium()?
+        .load_pdf_from_file(path, None)
+        .map_err(|e| match e {
+            PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::PasswordError) => {
+                format!("PDF is password protected: {}", path)
+            }
+            e => format!("Failed to open PDF: {}", e),
+        })?;
+
+    Ok(Document { document, _lock: lock })
+}
+
+/// Render a page at the given resolution
+pub fn render_page(page: &PdfPage, dpi: u32) -> Result<DynamicImage, String> {
+    let config = PdfRenderConfig::new()
+        .scale_page_by_factor(dpi as f32 / 72.0)
+        .render_form_data(true)
+        .render_annotations(true);
+
+    page.render_with_config(&config)
+        .map(|bitmap| bitmap.as_image())
+        .map_err(|e| format!("Failed to render page: {}", e))
+}
+
+/// The resolution, up to `dpi`, at which a page stays within the pixel
+/// limit for exports
+pub fn capped_dpi(page: &PdfPage, dpi: u32) -> u32 {
+    let points = (page.width().value * page.height().value).max(1.0);
+    let limit = (MAX_EXPORT_PIXELS / points).sqrt() * 72.0;
+    dpi.min(limit as u32).max(1)
+}
+
+/// Render a page scaled to fit within a square of `max_size` pixels
+pub fn render_thumbnail(page: &PdfPage, max_size: u32) -> Result<DynamicImage, String> {
+    let max_size = max_size as i32;
+    let config = PdfRenderConfig::new()
+        .set_target_width(max_size)
+        .set_maximum_width(max_size)
+        .set_maximum_height(max_size)
+        .render_form_data(true)
+        .render_annotations(true);
+
+    page.render_with_config(&config)
+        .map(|bitmap| bitmap.as_image())
+        .map_err(|e| format!("Failed to render page: {}", e))
+}
+
+/// Render a page for image export.
+///
+/// A target width and/or height takes precedence over the DPI; with both
+/// set the page is fitted inside that box, keeping its aspect ratio.
+pub fn render_for_export(page: &PdfPage, options: &ConvertOptions) -> Result<DynamicImage, String> {
+    let config = match (options.width, options.height) {
+        (Some(width), Some(height)) => PdfRenderConfig::new()
+            .set_target_width(width as i32)
+            .set_maximum_width(width as i32)
+            .set_maximum_height(height as i32),
+        (Some(width), None) => PdfRenderConfig::new().set_target_width(width as i32),
+        (None, Some(height)) => PdfRenderConfig::new().set_target_height(height as i32),
+        (None, None) => PdfRenderConfig::new().scale_page_by_factor(options.dpi as f32 / 72.0),
+    };
+
+    let (page_width, page_height) = (page.width().value, page.height().value);
+    let scale = match (options.width, options.height) {
+        (Some(width), Some(height)) => (width as f32 / page_width).min(height as f32 / page_height),
+        (Some(width), None) => width as f32 / page_width,
+        (None, Some(height)) => height as f32 / page_height,
+        (None, None) => options.dpi as f32 / 72.0,
+    };
+    let pixels = page_width * scale * page_height * scale;
+    let config = if pixels > MAX_EXPORT_PIXELS {
+        let limit = scale * (MAX_EXPORT_PIXELS / pixels).sqrt();
+        config
+            .set_maximum_width((page_width * limit) as i32)
+            .set_maximum_height((page_height * limit) as i32)
+    } else {
+        config
+    };
+
+    let antialias = options.antialias.unwrap_or(true);
+    let background = if raster::keeps_alpha(options)? {
+        PdfColor::new(255, 255, 255, 0)
+    } else {
+        PdfColor::WHITE
+    };
+
+    let config = config
+        .set_clear_color(background)
+        .set_text_smoothing(antialias)
+        .set_image_smoothing(antialias)
+        .set_path_smoothing(antialias)
+        .render_form_data(true)
+        .render_annotations(true);
+
+    page.render_with_config(&config)
+        .map(|bitmap| bitmap.as_image())
+        .map_err(|e| format!("Failed to render page: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn pdfium_lock_nests_and_excludes_other_threads() {
+        let outer = PdfiumLock::acquire();
+        let inner = PdfiumLock::acquire();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                let _lock = PdfiumLock::acquire();
+                sender.send(()).unwrap();
+            });
+            drop(inner);
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+            drop(outer);
+            assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+        });
+    }
+}
@@ -0,0 +1,309 @@
+use super::{render, text};
+use crate::commands::{SearchHit, SearchOptions, SearchRect, SearchResult};
+use pdfium_render::prelude::*;
+use regex::{Regex, RegexBuilder};
+use text::TextChar;
+use unicode_normalization::char::canonical_combining_class;
+use unicode_normalization::UnicodeNormalization;
+
+/// Searches stop collecting hits past this many
+const MAX_HITS: usize = 1000;
+
+/// Characters of surrounding text shown on each side of a hit
+const CONTEXT_CHARS: usize = 30;
+
+/// Full-width forms of the half-width katakana and punctuation, U+FF61 to U+FF9F
+const HALF_WIDTH_KANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン゛゜";
+
+/// Usual forms of the full-width symbols U+FFE0 to U+FFE6
+const FULL_WIDTH_SYMBOLS: [char; 7] = ['¢', '£', '¬', '¯', '¦', '¥', '₩'];
+
+/// Search the text of every page for a literal query or a regular expression
+pub fn search(path: &str, query: &str, options: &SearchOptions) -> Result<SearchResult, String> {
+    if query.trim().is_empty() {
+        return Err("Search query is empty".to_string());
+    }
+
+    let pattern = build_pattern(query, options)?;
+    let doc = render::open(path)?;
+
+    let mut hits = Vec::new();
+    let mut truncated = false;
+
+    'pages: for (index, page) in doc.pages().iter().enumerate() {
+        let chars = text::page_chars(&page)?;
+        let (haystack, origins) = normalize_text(&chars, options.ignore_width);
+        let page_width = page.width().value;
+        let page_height = page.height().value;
+        let frame = PageFrame::of(&page);
+
+        for found in pattern.find_iter(&haystack) {
+            if found.is_empty() {
+                continue;
+            }
+            if hits.len() == MAX_HITS {
+                truncated = true;
+                break 'pages;
+            }
+
+            let start = origins[found.start()];
+            let end = origins[found.end() - 1] + 1;
+            hits.push(SearchHit {
+                page: index as u32 + 1,
+                text: chars[start..end].iter().map(|c| c.ch).collect(),
+                context: context(&chars, start, end),
+                rects: hit_rects(&chars[start..end], &frame),
+                page_width,
+                page_height,
+            });
+        }
+    }
+
+    Ok(SearchResult {
+        path: path.to_string(),
+        hits,
+        truncated,
+    })
+}
+
+fn build_pattern(query: &str, options: &SearchOptions) -> Result<Regex, String> {
+    let pattern = match (options.regex, options.ignore_width) {
+        (true, true) => fold_pattern(query),
+        (true, false) => query.to_string(),
+        (false, true) => regex::escape(&fold(query)),
+        (false, false) => regex::escape(query),
+    };
+
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid regular expression: {}", e))
+}
+
+/// Split text into clusters of a base character and the marks that follow
+/// it, so half-width kana and their voiced sound marks fold together
+fn clusters(chars: &[char]) -> Vec<(usize, usize)> {
+    let mut clusters: Vec<(usize, usize)> = Vec::new();
+    for (i, &ch) in chars.iter().enumerate() {
+        match clusters.last_mut() {
+            Some(last) if is_mark(ch) => last.1 = i + 1,
+            _ => clusters.push((i, i + 1)),
+        }
+    }
+    clusters
+}
+
+fn is_mark(ch: char) -> bool {
+    canonical_combining_class(ch) != 0 || matches!(ch, '\u{FF9E}' | '\u{FF9F}')
+}
+
+/// Fold full-width ASCII and half-width kana to their usual forms.
+///
+/// Only width is folded: unlike NFKC, this leaves characters such as ①
+/// and ㍻ alone. Half-width voiced sound marks join the kana before them.
+fn fold(text: &str) -> String {
+    let folded: String = text.chars()
+        .enumerate()
+        .map(|(i, ch)| match ch {
+            '\u{FF9E}' if i > 0 => '\u{3099}',
+            '\u{FF9F}' if i > 0 => '\u{309A}',
+            ch => fold_width(ch),
+        })
+        .collect();
+    folded.nfc().collect()
+}
+
+fn fold_width(ch: char) -> char {
+    match ch {
+        '\u{3000}' => ' ',
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(ch as u32 - 0xFEE0).unwrap_or(ch),
+        '\u{FF61}'..='\u{FF9F}' => HALF_WIDTH_KANA.chars().nth((ch as u32 - 0xFF61) as usize).unwrap_or(ch),
+        '\u{FFE0}'..='\u{FFE6}' => FULL_WIDTH_SYMBOLS[(ch as u32 - 0xFFE0) as usize],
+        _ => ch,
+    }
+}
+
+/// Fold only the literal non-ASCII parts of a regex, leaving its syntax alone.
+///
+/// A full-width "（" folds to "(", so folded literals are escaped to keep
+/// them from turning into regex syntax.
+fn fold_pattern(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut folded = String::new();
+    for (start, end) in clusters(&chars) {
+        let cluster: String = chars[start..end].iter().collect();
+        if cluster.is_ascii() {
+            folded.push_str(&cluster);
+        } else {
+            folded.push_str(&regex::escape(&fold(&cluster)));
+        }
+    }
+    folded
+}
+
+/// Page text prepared for matching, with the source character index of
+/// every byte so matches can be traced back to character positions.
+///
+/// Line breaks become single spaces so phrases match across lines.
+fn normalize_text(chars: &[TextChar], ignore_width: bool) -> (String, Vec<usize>) {
+    let plain: Vec<char> = chars.iter().map(|c| c.ch).collect();
+    let mut haystack = String::with_capacity(plain.len());
+    let mut origins = Vec::with_capacity(plain.len());
+
+    let mut push = |text: &str, origin: usize, haystack: &mut String| {
+        haystack.push_str(text);
+        origins.extend(std::iter::repeat_n(origin, text.len()));
+    };
+
+    let mut buffer = [0u8; 4];
+    for (start, end) in clusters(&plain) {
+        if plain[start] == '\r' || plain[start] == '\n' {
+            if !haystack.ends_with(' ') {
+                push(" ", start, &mut haystack);
+            }
+            continue;
+        }
+
+        let cluster: String = plain[start..end].iter().collect();
+        if ignore_width {
+            let folded = fold(&cluster);
+            // Attribute the folded text to the cluster's first and last characters
+            for (i, ch) in folded.chars().enumerate() {
+                let origin = if i == 0 { start } else { end - 1 };
+                push(ch.encode_utf8(&mut buffer), origin, &mut haystack);
+            }
+        } else {
+            for (offset, ch) in cluster.chars().enumerate() {
+                push(ch.encode_utf8(&mut buffer), start + offset, &mut haystack);
+            }
+        }
+    }
+
+    (haystack, origins)
+}
+
+fn context(chars: &[TextChar], start: usize, end: usize) -> String {
+    let from = start.saturating_sub(CONTEXT_CHARS);
+    let to = (end + CONTEXT_CHARS).min(chars.len());
+
+    let mut context = String::new();
+    for c in &chars[from..to] {
+        let ch = if c.ch.is_whitespace() { ' ' } else { c.ch };
+        if !(ch == ' ' && context.ends_with(' ')) {
+            context.push(ch);
+        }
+    }
+    context.trim().to_string()
+}
+
+/// How a page is shown: its visible box in page space, and how far it is
+/// turned clockwise
+struct PageFrame {
+    bounds: [f32; 4],
+    rotation: u32,
+}
+
+impl PageFrame {
+    fn of(page: &PdfPage) -> Self {
+        let boundaries = page.boundaries();
+        let bounds = boundaries.crop()
+            .or_else(|_| boundaries.media())
+            .map(|boundary| {
+                let rect = boundary.bounds;
+                [rect.left().value, rect.bottom().value, rect.right().value, rect.top().value]
+            })
+            .unwrap_or([0.0, 0.0, page.width().value, page.height().value]);
+        let rotation = match page.rotation() {
+            Ok(PdfPageRenderRotation::Degrees90) => 90,
+            Ok(PdfPageRenderRotation::Degrees180) => 180,
+            Ok(PdfPageRenderRotation::Degrees270) => 270,
+            _ => 0,
+        };
+        Self { bounds, rotation }
+    }
+
+    /// A point in page space, as points from the top-left corner of the
+    /// page as shown
+    fn shown(&self, x: f32, y: f32) -> (f32, f32) {
+        let [x0, y0, x1, y1] = self.bounds;
+        match self.rotation {
+            90 => (y - y0, x - x0),
+            180 => (x1 - x, y - y0),
+            270 => (y1 - y, x1 - x),
+            _ => (x - x0, y1 - y),
+        }
+    }
+
+    /// A rectangle in page space as left, top, right, bottom on the page as shown
+    fn shown_rect(&self, [left, bottom, right, top]: [f32; 4]) -> [f32; 4] {
+        let (ax, ay) = self.shown(left, bottom);
+        let (bx, by) = self.shown(right, top);
+        [ax.min(bx), ay.min(by), ax.max(bx), ay.max(by)]
+    }
+}
+
+/// Highlight rectangles for a hit, one per line it spans, measured in
+/// points from the top-left corner of the page as shown
+fn hit_rects(chars: &[TextChar], frame: &PageFrame) -> Vec<SearchRect> {
+    let mut lines: Vec<[f32; 4]> = Vec::new();
+
+    for [left, top, right, bottom] in chars.iter().filter_map(|c| c.bounds).map(|bounds| frame.shown_rect(bounds)) {
+        let middle = (top + bottom) / 2.0;
+        match lines.last_mut() {
+            Some(line) if middle >= line[1] && middle <= line[3] && left >= line[0] => {
+                line[0] = line[0].min(left);
+                line[1] = line[1].min(top);
+                line[2] = line[2].max(right);
+                line[3] = line[3].max(bottom);
+            }
+            _ => lines.push([left, top, right, bottom]),
+        }
+    }
+
+    lines.into_iter()
+        .map(|[left, top, right, bottom]| SearchRect {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fold_covers_width_only() {
+        assert_eq!(fold("ＦｌｕｘＦｏｒｇｅ　（ＰＤＦ）"), "FluxForge (PDF)");
+        assert_eq!(fold("ﾃﾞｰﾀ ﾊﾟｽ ｳﾞ"), "データ パス ヴ");
+        assert_eq!(fold("￥１００"), "¥100");
+        assert_eq!(fold("①㍻ｶ"), "①㍻カ");
+    }
+
+    #[test]
+    fn half_width_kana_match_their_full_width_forms() {
+        // NFKC spells the two standalone sound marks with a leading space
+        for (i, full) in HALF_WIDTH_KANA.chars().enumerate().take(61) {
+            let half = char::from_u32(0xFF61 + i as u32).unwrap();
+            assert_eq!(half.to_string().nfkc().collect::<String>(), full.to_string().nfkc().collect::<String>());
+        }
+    }
+
+    #[test]
+    fn rects_follow_box_origin_and_rotation() {
+        let char_at = |bounds| TextChar { ch: 'a', bounds: Some(bounds) };
+        let chars = [char_at([110.0, 230.0, 120.0, 240.0])];
+        let rect = |rotation| {
+            let frame = PageFrame { bounds: [100.0, 200.0, 400.0, 600.0], rotation };
+            let rect = &hit_rects(&chars, &frame)[0];
+            [rect.x, rect.y, rect.width, rect.height]
+        };
+
+        assert_eq!(rect(0), [10.0, 360.0, 10.0, 10.0]);
+        assert_eq!(rect(90), [30.0, 10.0, 10.0, 10.0]);
+        assert_eq!(rect(180), [280.0, 30.0, 10.0, 10.0]);
+        assert_eq!(rect(270), [360.0, 280.0, 10.0, 10.0]);
+    }
+}
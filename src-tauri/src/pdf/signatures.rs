@@ -0,0 +1,644 @@
+use super::pdfa;
+use crate::commands::{PdfSignature, SignatureReport};
+use lopdf::{decode_text_string, Dictionary, Document, Object, ObjectId};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+/// Field trees nested deeper than this are treated as malformed
+const MAX_FIELD_DEPTH: usize = 32;
+
+/// BER elements nested deeper than this are treated as malformed
+const MAX_DER_DEPTH: usize = 64;
+
+const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+const OID_MESSAGE_DIGEST: &str = "1.2.840.113549.1.9.4";
+const OID_SIGNING_TIME: &str = "1.2.840.113549.1.9.5";
+const OID_TIMESTAMP_TOKEN: &str = "1.2.840.113549.1.9.16.2.14";
+
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+
+/// A signature field, with the signature dictionary when it has been signed
+struct SignatureField<'a> {
+    name: String,
+    widgets: Vec<ObjectId>,
+    value: Option<&'a Dictionary>,
+}
+
+/// List the signature fields of a PDF and check each signature against
+/// the bytes it covers.
+///
+/// Everything is read from the file itself: certificates are reported but
+/// not checked for revocation, so no OCSP or CRL service is contacted.
+pub fn inspect_signatures(path: &str) -> Result<SignatureReport, String> {
+    let doc = super::load_decrypted(path)?;
+    // Byte ranges refer to the file as stored, not as parsed
+    let data = fs::read(path)
+        .map_err(|e| format!("Failed to read PDF: {}", e))?;
+
+    let mut fields = Vec::new();
+    let roots = doc.catalog()
+        .and_then(|catalog| catalog.get_deref(b"AcroForm", &doc))
+        .and_then(Object::as_dict)
+        .and_then(|acro_form| acro_form.get_deref(b"Fields", &doc))
+        .and_then(Object::as_array);
+    if let Ok(roots) = roots {
+        let mut seen = HashSet::new();
+        for root in roots {
+            collect_fields(&doc, root, "", None, &mut fields, &mut seen, 0);
+        }
+    }
+
+    let pages = annotation_pages(&doc);
+    let signatures: Vec<PdfSignature> = fields.into_iter()
+        .map(|field| inspect(&doc, &data, &pages, field))
+        .collect();
+    let modified = signatures.iter()
+        .any(|signature| signature.modified_after_signing || signature.intact == Some(false));
+
+    Ok(SignatureReport {
+        path: path.to_string(),
+        signatures,
+        modified,
+    })
+}
+
+/// Walk the field tree, collecting terminal fields of type Sig.
+/// Names are joined from the partial names of their ancestors.
+fn collect_fields<'a>(
+    doc: &'a Document,
+    object: &'a Object,
+    parent_name: &str,
+    parent_type: Option<&'a [u8]>,
+    fields: &mut Vec<SignatureField<'a>>,
+    seen: &mut HashSet<ObjectId>,
+    depth: usize,
+) {
+    if depth > MAX_FIELD_DEPTH {
+        return;
+    }
+    let Ok((id, Object::Dictionary(field))) = doc.dereference(object) else {
+        return;
+    };
+    if id.is_some_and(|id| !seen.insert(id)) {
+        return;
+    }
+
+    let name = match field.get(b"T").and_then(decode_text_string) {
+        Ok(partial) if parent_name.is_empty() => partial,
+        Ok(partial) => format!("{}.{}", parent_name, partial),
+        Err(_) => parent_name.to_string(),
+    };
+    // The field type is inheritable
+    let field_type = field.get(b"FT").and_then(Object::as_name).ok().or(parent_type);
+
+    // Kids with a partial name are fields of their own; the rest are widgets
+    let kids = field.get_deref(b"Kids", doc).and_then(Object::as_array).map_or(&[][..], Vec::as_slice);
+    let is_field = |kid: &Object| doc.dereference(kid).is_ok_and(|(_, kid)| kid.as_dict().is_ok_and(|kid| kid.has(b"T")));
+    if kids.iter().any(is_field) {
+        for kid in kids.iter().filter(|kid| is_field(kid)) {
+            collect_fields(doc, kid, &name, field_type, fields, seen, depth + 1);
+        }
+        return;
+    }
+
+    if field_type != Some(b"Sig") {
+        return;
+    }
+    let widgets = id.into_iter()
+        .chain(kids.iter().filter_map(|kid| kid.as_reference().ok()))
+        .collect();
+    fields.push(SignatureField {
+        name,
+        widgets,
+        value: field.get_deref(b"V", doc).and_then(Object::as_dict).ok(),
+    });
+}
+
+/// Page number of every annotation placed on a page
+fn annotation_pages(doc: &Document) -> HashMap<ObjectId, u32> {
+    let mut pages = HashMap::new();
+    for (page_number, page_id) in doc.get_pages() {
+        let annotations = doc.get_dictionary(page_id)
+            .and_then(|page| page.get_deref(b"Annots", doc))
+            .and_then(Object::as_array);
+        for annotation in annotations.into_iter().flatten() {
+            if let Ok(id) = annotation.as_reference() {
+                pages.entry(id).or_insert(page_number);
+            }
+        }
+    }
+    pages
+}
+
+fn inspect(doc: &Document, data: &[u8], pages: &HashMap<ObjectId, u32>, field: SignatureField) -> PdfSignature {
+    let mut signature = PdfSignature {
+        field: field.name,
+        page: field.widgets.iter().find_map(|id| pages.get(id).copied()),
+        ..Default::default()
+    };
+    let Some(value) = field.value else {
+        return signature;
+    };
+
+    let text = |key: &[u8]| value.get_deref(key, doc).and_then(decode_text_string).ok();
+    signature.signed = true;
+    signature.sub_filter = value.get(b"SubFilter")
+        .and_then(Object::as_name)
+        .map(|name| String::from_utf8_lossy(name).to_string())
+        .ok();
+    signature.signer_name = text(b"Name");
+    signature.reason = text(b"Reason");
+    signature.location = text(b"Location");
+    signature.byte_range = value.get_deref(b"ByteRange", doc)
+        .and_then(Object::as_array)
+        .map(|range| range.iter().filter_map(|n| n.as_i64().ok()).collect())
+        .unwrap_or_default();
+
+    let Some(SignedRange { parts, contents, end }) = signed_range(data, &signature.byte_range) else {
+        return signature;
+    };
+    // Anything past the signed range but trailing whitespace is a later revision
+    signature.modified_after_signing = data[end..].iter().any(|b| !b.is_ascii_whitespace());
+
+    let sub_filter = signature.sub_filter.clone().unwrap_or_default();
+    if sub_filter == "adbe.x509.rsa_sha1" {
+        // The certificate travels in the dictionary and the digest inside the
+        // bare RSA signature, which cannot be opened without the public key math
+        let certificate = match value.get_deref(b"Cert", doc) {
+            Ok(Object::String(bytes, _)) => Some(bytes),
+            // A chain, signer first
+            Ok(Object::Array(chain)) => match chain.first() {
+                Some(Object::String(bytes, _)) => Some(bytes),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some((issuer, _, subject)) = certificate.and_then(|bytes| certificate_names(bytes)) {
+            signature.subject = Some(distinguished_name(&subject));
+            signature.issuer = Some(distinguished_name(&issuer));
+        }
+        signature.digest_algorithm = Some("SHA-1".to_string());
+        signature.signing_time = text(b"M").and_then(|date| pdfa::pdf_date_to_xmp(&date));
+        return signature;
+    }
+
+    let Some(cms) = SignedData::parse(&contents) else {
+        signature.signing_time = text(b"M").and_then(|date| pdfa::pdf_date_to_xmp(&date));
+        return signature;
+    };
+    let signer = cms.signer.as_ref();
+
+    if let Some((issuer, _, subject)) = cms.signer_certificate() {
+        signature.subject = Some(distinguished_name(&subject));
+        signature.issuer = Some(distinguished_name(&issuer));
+    }
+    // The signed attribute is covered by the signature, the dictionary's M is not
+    signature.signing_time = signer
+        .and_then(|signer| signer.attribute(OID_SIGNING_TIME))
+        .and_then(|time| der_time(&time))
+        .or_else(|| text(b"M").and_then(|date| pdfa::pdf_date_to_xmp(&date)));
+
+    let (algorithm, expected) = if sub_filter == "ETSI.RFC3161" {
+        // A document timestamp: the token itself holds the document digest
+        let Some(stamp) = cms.content.as_deref().and_then(TimeStamp::parse) else {
+            return signature;
+        };
+        signature.timestamp = stamp.time.clone();
+        (stamp.algorithm, Some(stamp.imprint))
+    } else {
+        signature.timestamp = signer
+            .and_then(|signer| signer.unsigned_attribute(OID_TIMESTAMP_TOKEN))
+            .and_then(|token| SignedData::parse(token.raw))
+            .and_then(|token| token.content.as_deref().and_then(TimeStamp::parse))
+            .and_then(|stamp| stamp.time);
+
+        let algorithm = signer.and_then(|signer| signer.digest_algorithm);
+        if sub_filter == "adbe.pkcs7.sha1" {
+            // The SHA-1 digest of the document is the signed content
+            (Some("SHA-1"), cms.content.clone())
+        } else {
+            let digest = signer
+                .and_then(|signer| signer.attribute(OID_MESSAGE_DIGEST))
+                .map(|digest| octets(&digest));
+            (algorithm, digest)
+        }
+    };
+
+    signature.digest_algorithm = algorithm.map(str::to_string);
+    if let (Some(algorithm), Some(expected)) = (algorithm, expected) {
+        signature.intact = digest(algorithm, &parts).map(|actual| actual == expected);
+    }
+    signature
+}
+
+/// What a ByteRange splits the file into
+struct SignedRange<'a> {
+    /// The two signed parts of the file
+    parts: [&'a [u8]; 2],
+    /// The signature held in the gap between them
+    contents: Vec<u8>,
+    /// Offset where the signed range ends
+    end: usize,
+}
+
+fn signed_range<'a>(data: &'a [u8], byte_range: &[i64]) -> Option<SignedRange<'a>> {
+    let [start1, length1, start2, length2] = byte_range
+        .iter()
+        .map(|&n| usize::try_from(n).ok())
+        .collect::<Option<Vec<_>>>()?[..]
+    else {
+        return None;
+    };
+
+    // The signature must cover the file from its very first byte
+    let end1 = start1.checked_add(length1)?;
+    let end2 = start2.checked_add(length2)?;
+    if start1 != 0 || end1 > start2 || end2 > data.len() {
+        return None;
+    }
+
+    // The gap holds exactly the hex string of /Contents
+    let gap = data[end1..start2].trim_ascii();
+    let hex = gap.strip_prefix(b"<")?.strip_suffix(b">")?;
+    let contents = decode_hex(hex)?;
+
+    Some(SignedRange {
+        parts: [&data[start1..end1], &data[start2..end2]],
+        contents,
+        end: end2,
+    })
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = hex.iter()
+        .filter(|b| !b.is_ascii_whitespace())
+        .map(|&b| (b as char).to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()?;
+    // An odd final digit is followed by an implied zero
+    Some(digits.chunks(2).map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0)).collect())
+}
+
+fn digest(algorithm: &str, parts: &[&[u8]]) -> Option<Vec<u8>> {
+    fn hash<D: Digest>(parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = D::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().to_vec()
+    }
+
+    match algorithm {
+        "SHA-1" => Some(hash::<Sha1>(parts)),
+        "SHA-256" => Some(hash::<Sha256>(parts)),
+        "SHA-384" => Some(hash::<Sha384>(parts)),
+        "SHA-512" => Some(hash::<Sha512>(parts)),
+        _ => None,
+    }
+}
+
+/// Digest algorithm named by an AlgorithmIdentifier
+fn digest_name(algorithm: &Tlv) -> Option<&'static str> {
+    let children = algorithm.children();
+    let identifier = children.first().filter(|oid| oid.tag == TAG_OID)?;
+    match oid(identifier.value).as_str() {
+        "1.3.14.3.2.26" => Some("SHA-1"),
+        "2.16.840.1.101.3.4.2.1" => Some("SHA-256"),
+        "2.16.840.1.101.3.4.2.2" => Some("SHA-384"),
+        "2.16.840.1.101.3.4.2.3" => Some("SHA-512"),
+        // Some signers name the signature algorithm instead of the digest
+        "1.2.840.113549.1.1.5" => Some("SHA-1"),
+        "1.2.840.113549.1.1.11" => Some("SHA-256"),
+        "1.2.840.113549.1.1.12" => Some("SHA-384"),
+        "1.2.840.113549.1.1.13" => Some("SHA-512"),
+        _ => None,
+    }
+}
+
+/// A BER-encoded element: its tag, its contents, and the whole encoding
+#[derive(Clone, Copy)]
+struct Tlv<'a> {
+    tag: u8,
+    value: &'a [u8],
+    raw: &'a [u8],
+}
+
+impl<'a> Tlv<'a> {
+    /// Read the element at the start of `data`, returning it and what follows
+    fn read(data: &'a [u8]) -> Option<(Tlv<'a>, &'a [u8])> {
+        Self::read_nested(data, 0)
+    }
+
+    fn read_nested(data: &'a [u8], depth: usize) -> Option<(Tlv<'a>, &'a [u8])> {
+        if depth > MAX_DER_DEPTH {
+            return None;
+        }
+        let (&tag, rest) = data.split_first()?;
+        // Tag numbers above 30 take extra bytes and never occur in CMS
+        if tag & 0x1f == 0x1f {
+            return None;
+        }
+        let (&first, rest) = rest.split_first()?;
+
+        if first == 0x80 {
+            // Indefinite length: elements up to an end-of-contents marker
+            if tag & 0x20 == 0 {
+                return None;
+            }
+            let mut inner = rest;
+            while !inner.starts_with(&[0, 0]) {
+                inner = Self::read_nested(inner, depth + 1)?.1;
+            }
+            let value = &rest[..rest.len() - inner.len()];
+            let after = &inner[2..];
+            let raw = &data[..data.len() - after.len()];
+            return Some((Tlv { tag, value, raw }, after));
+        }
+
+        let (length, rest) = if first & 0x80 == 0 {
+            (first as usize, rest)
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count > 4 || rest.len() < count {
+                return None;
+            }
+            let (bytes, rest) = rest.split_at(count);
+            (bytes.iter().fold(0usize, |n, &b| n << 8 | b as usize), rest)
+        };
+        if rest.len() < length {
+            return None;
+        }
+
+        let header = data.len() - rest.len();
+        let tlv = Tlv {
+            tag,
+            value: &rest[..length],
+            raw: &data[..header + length],
+        };
+        Some((tlv, &rest[length..]))
+    }
+
+    /// Elements of a constructed element, up to the first malformed one
+    fn children(&self) -> Vec<Tlv<'a>> {
+        let mut children = Vec::new();
+        let mut rest = self.value;
+        while let Some((child, after)) = Self::read(rest) {
+            children.push(child);
+            rest = after;
+        }
+        children
+    }
+}
+
+/// Contents of an OCTET STRING, which BER may split into segments
+fn octets(tlv: &Tlv) -> Vec<u8> {
+    if tlv.tag & 0x20 == 0 {
+        tlv.value.to_vec()
+    } else {
+        tlv.children().iter().flat_map(|segment| segment.value.iter().copied()).collect()
+    }
+}
+
+/// Dotted form of an OBJECT IDENTIFIER
+fn oid(bytes: &[u8]) -> String {
+    let mut arcs: Vec<u64> = Vec::new();
+    let mut value: u64 = 0;
+    for &byte in bytes {
+        value = value << 7 | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            if arcs.is_empty() {
+                // The first two arcs share one number
+                let first = (value / 40).min(2);
+                arcs.extend([first, value - first * 40]);
+            } else {
+                arcs.push(value);
+            }
+            value = 0;
+        }
+    }
+    arcs.iter().map(u64::to_string).collect::<Vec<_>>().join(".")
+}
+
+/// ISO 8601 form of a UTCTime or GeneralizedTime
+fn der_time(tlv: &Tlv) -> Option<String> {
+    let text = std::str::from_utf8(tlv.value).ok()?;
+    let (year, rest) = match tlv.tag {
+        0x17 => {
+            let year: u32 = text.get(..2)?.parse().ok()?;
+            (if year >= 50 { 1900 + year } else { 2000 + year }, &text[2..])
+        }
+        0x18 => (text.get(..4)?.parse().ok()?, &text[4..]),
+        _ => return None,
+    };
+
+    let field = |start: usize| rest.get(start..start + 2)
+        .filter(|s| s.chars().all(|c| c.is_ascii_digit()));
+    Some(format!(
+        "{:04}-{}-{}T{}:{}:{}Z",
+        year, field(0)?, field(2)?, field(4)?, field(6)?, field(8).unwrap_or("00")
+    ))
+}
+
+/// Readable form of an X.501 Name, most specific attribute first
+fn distinguished_name(name: &Tlv) -> String {
+    let mut parts = Vec::new();
+    for relative in name.children() {
+        for attribute in relative.children() {
+            if let [key, value] = &attribute.children()[..] {
+                parts.push(format!("{}={}", attribute_label(&oid(key.value)), string_value(value)));
+            }
+        }
+    }
+    parts.reverse();
+    parts.join(", ")
+}
+
+fn attribute_label(oid: &str) -> String {
+    match oid {
+        "2.5.4.3" => "CN",
+        "2.5.4.5" => "SERIALNUMBER",
+        "2.5.4.6" => "C",
+        "2.5.4.7" => "L",
+        "2.5.4.8" => "ST",
+        "2.5.4.9" => "STREET",
+        "2.5.4.10" => "O",
+        "2.5.4.11" => "OU",
+        "2.5.4.12" => "T",
+        "1.2.840.113549.1.9.1" => "E",
+        other => other,
+    }
+    .to_string()
+}
+
+fn string_value(tlv: &Tlv) -> String {
+    match tlv.tag {
+        // UTF8String, PrintableString, IA5String, VisibleString, NumericString
+        0x0c | 0x13 | 0x16 | 0x1a | 0x12 => String::from_utf8_lossy(tlv.value).to_string(),
+        // TeletexString, read as Latin-1 like most software does
+        0x14 => tlv.value.iter().map(|&b| b as char).collect(),
+        // BMPString
+        0x1e => {
+            let units: Vec<u16> = tlv.value.chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => format!("#{}", tlv.value.iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+    }
+}
+
+/// Issuer, serial number and subject of an encoded certificate
+fn certificate_names(certificate: &[u8]) -> Option<(Tlv<'_>, Tlv<'_>, Tlv<'_>)> {
+    let (certificate, _) = Tlv::read(certificate)?;
+    let to_be_signed = certificate.children().into_iter().next()?;
+    let mut fields = to_be_signed.children().into_iter().peekable();
+    // Skip the explicitly tagged version
+    fields.next_if(|field| field.tag == 0xa0);
+
+    let serial = fields.next()?;
+    let _signature_algorithm = fields.next()?;
+    let issuer = fields.next()?;
+    let _validity = fields.next()?;
+    let subject = fields.next()?;
+    Some((issuer, serial, subject))
+}
+
+/// The parts of a CMS SignedData that say what was signed and by whom
+struct SignedData<'a> {
+    certificates: Vec<Tlv<'a>>,
+    content: Option<Vec<u8>>,
+    signer: Option<SignerInfo<'a>>,
+}
+
+struct SignerInfo<'a> {
+    issuer: Option<Tlv<'a>>,
+    serial: Option<Tlv<'a>>,
+    digest_algorithm: Option<&'static str>,
+    attributes: Vec<Tlv<'a>>,
+    unsigned_attributes: Vec<Tlv<'a>>,
+}
+
+impl<'a> SignedData<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let (info, _) = Tlv::read(data)?;
+        let parts = info.children();
+        let content_type = parts.first().filter(|part| part.tag == TAG_OID)?;
+        if oid(content_type.value) != OID_SIGNED_DATA {
+            return None;
+        }
+        let signed_data = parts.get(1)
+            .filter(|part| part.tag == 0xa0)?
+            .children()
+            .into_iter()
+            .next()?;
+
+        let fields = signed_data.children();
+        let content = fields.get(2)
+            .and_then(|encapsulated| encapsulated.children().get(1).copied())
+            .filter(|content| content.tag == 0xa0)
+            .and_then(|content| content.children().first().map(octets));
+        let certificates = fields.iter()
+            .skip(3)
+            .find(|field| field.tag == 0xa0)
+            .map(Tlv::children)
+            .unwrap_or_default();
+        let signer = fields.iter()
+            .skip(3)
+            .rfind(|field| field.tag == TAG_SET)
+            .and_then(|signers| signers.children().into_iter().next())
+            .map(|signer| SignerInfo::parse(&signer));
+
+        Some(Self {
+            certificates,
+            content,
+            signer,
+        })
+    }
+
+    /// Names of the certificate the signer identifies, or of the first one
+    fn signer_certificate(&self) -> Option<(Tlv<'a>, Tlv<'a>, Tlv<'a>)> {
+        let mut names = self.certificates.iter().filter_map(|certificate| certificate_names(certificate.raw));
+        let signer = self.signer.as_ref();
+        let identified = names.clone().find(|(issuer, serial, _)| {
+            signer.is_some_and(|signer| {
+                signer.issuer.is_some_and(|id| id.raw == issuer.raw)
+                    && signer.serial.is_some_and(|id| id.value == serial.value)
+            })
+        });
+        identified.or_else(|| names.next())
+    }
+}
+
+impl<'a> SignerInfo<'a> {
+    fn parse(signer: &Tlv<'a>) -> Self {
+        let fields = signer.children();
+        // Identified by issuer and serial number, or by subject key identifier
+        let (issuer, serial) = match fields.get(1) {
+            Some(id) if id.tag == TAG_SEQUENCE => {
+                let parts = id.children();
+                (parts.first().copied(), parts.get(1).copied())
+            }
+            _ => (None, None),
+        };
+        let tagged = |tag: u8| fields.iter()
+            .skip(3)
+            .find(|field| field.tag == tag)
+            .map(Tlv::children)
+            .unwrap_or_default();
+
+        Self {
+            issuer,
+            serial,
+            digest_algorithm: fields.get(2).and_then(digest_name),
+            attributes: tagged(0xa0),
+            unsigned_attributes: tagged(0xa1),
+        }
+    }
+
+    /// First value of a signed attribute
+    fn attribute(&self, id: &str) -> Option<Tlv<'a>> {
+        find_attribute(&self.attributes, id)
+    }
+
+    fn unsigned_attribute(&self, id: &str) -> Option<Tlv<'a>> {
+        find_attribute(&self.unsigned_attributes, id)
+    }
+}
+
+fn find_attribute<'a>(attributes: &[Tlv<'a>], id: &str) -> Option<Tlv<'a>> {
+    attributes.iter().find_map(|attribute| {
+        let parts = attribute.children();
+        let key = parts.first().filter(|key| key.tag == TAG_OID)?;
+        if oid(key.value) != id {
+            return None;
+        }
+        parts.get(1).filter(|values| values.tag == TAG_SET)?.children().into_iter().next()
+    })
+}
+
+/// What an RFC 3161 timestamp token vouches for
+struct TimeStamp {
+    algorithm: Option<&'static str>,
+    imprint: Vec<u8>,
+    time: Option<String>,
+}
+
+impl TimeStamp {
+    /// Read a TSTInfo structure
+    fn parse(data: &[u8]) -> Option<Self> {
+        let (info, _) = Tlv::read(data)?;
+        let fields = info.children();
+        let imprint = fields.get(2)?.children();
+        let hashed = imprint.get(1).filter(|hashed| hashed.tag == TAG_OCTET_STRING)?;
+
+        Some(Self {
+            algorithm: imprint.first().and_then(digest_name),
+            imprint: hashed.value.to_vec(),
+            time: fields.get(4).and_then(der_time),
+        })
+    }
+}
@@ -0,0 +1,34 @@
+use pdfium_render::prelude::*;
+
+/// Extract the plain text of a page in reading order
+pub fn page_text(page: &PdfPage) -> Result<String, String> {
+    page.text()
+        .map(|text| text.all())
+        .map_err(|e| format!("Failed to extract text: {}", e))
+}
+
+/// A character of page text and where it was drawn
+pub struct TextChar {
+    pub ch: char,
+    /// Left, bottom, right, top in points; None for generated spacing
+    pub bounds: Option<[f32; 4]>,
+}
+
+/// Extract page text character by character, with positions
+pub fn page_chars(page: &PdfPage) -> Result<Vec<TextChar>, String> {
+    let text = page.text()
+        .map_err(|e| format!("Failed to extract text: {}", e))?;
+
+    let chars = text.chars()
+        .iter()
+        .filter_map(|c| {
+            let ch = c.unicode_char()?;
+            let bounds = c.loose_bounds().ok()
+                .map(|rect| [rect.left().value, rect.bottom().value, rect.right().value, rect.top().value])
+                .filter(|[left, bottom, right, top]| right > left || top > bottom);
+            Some(TextChar { ch, bounds })
+        })
+        .collect();
+
+    Ok(chars)
+}
@@ -0,0 +1,285 @@
+use super::render;
+use crate::commands::{PageThumbnail, ThumbnailOptions};
+use base64::Engine;
+use image::{DynamicImage, ImageFormat};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Thumbnails kept before the least recently used ones are dropped
+const MAX_ENTRIES: usize = 1000;
+const MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// PNG files kept on disk before the least recently handed out are deleted
+const MAX_FILES: usize = 1000;
+
+const MIN_SIZE: u32 = 32;
+const MAX_SIZE: u32 = 1024;
+
+static CACHE: Mutex<Option<ThumbnailCache>> = Mutex::new(None);
+
+/// A file is identified by its path and modification time, so editing a
+/// PDF invalidates its thumbnails without any explicit bookkeeping
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct FileKey {
+    path: PathBuf,
+    modified: SystemTime,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    file: FileKey,
+    page: u32,
+    size: u32,
+}
+
+struct CacheEntry {
+    png: Arc<Vec<u8>>,
+    width: u32,
+    height: u32,
+    last_used: u64,
+}
+
+/// In-memory LRU of rendered thumbnails, mirrored to PNG files on request.
+///
+/// Files are tracked apart from the entries, by when each was last handed
+/// out: a response may name a file whose entry has already been dropped.
+struct ThumbnailCache {
+    dir: PathBuf,
+    entries: HashMap<CacheKey, CacheEntry>,
+    files: HashMap<CacheKey, u64>,
+    page_counts: HashMap<FileKey, u32>,
+    bytes: usize,
+    clock: u64,
+}
+
+impl ThumbnailCache {
+    /// Files left over from a previous run have no index entry, so start clean
+    fn new(dir: &Path) -> Self {
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                if entry.path().extension().is_some_and(|ext| ext == "png") {
+                    let _ = fs::remove_file(entry.path());
+                }
+            }
+        }
+
+        Self {
+            dir: dir.to_path_buf(),
+            entries: HashMap::new(),
+            files: HashMap::new(),
+            page_counts: HashMap::new(),
+            bytes: 0,
+            clock: 0,
+        }
+    }
+
+    fn touch(&mut self, key: &CacheKey) -> bool {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_used = self.clock;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, key: CacheKey, png: Arc<Vec<u8>>, width: u32, height: u32) {
+        self.clock += 1;
+        self.bytes += png.len();
+        let previous = self.entries.insert(key, CacheEntry {
+            png,
+            width,
+            height,
+            last_used: self.clock,
+        });
+        if let Some(previous) = previous {
+            self.bytes -= previous.png.len();
+        }
+
+        let mut evicted = false;
+        while self.entries.len() > MAX_ENTRIES || self.bytes > MAX_BYTES {
+            let oldest = self.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest.and_then(|key| self.entries.remove(&key)) {
+                Some(entry) => self.bytes -= entry.png.len(),
+                None => break,
+            }
+            evicted = true;
+        }
+        if evicted {
+            self.prune_page_counts();
+        }
+    }
+
+    fn insert_page_count(&mut self, file: FileKey, count: u32) {
+        self.page_counts.insert(file, count);
+        if self.page_counts.len() > MAX_ENTRIES {
+            self.prune_page_counts();
+        }
+    }
+
+    /// Forget the page counts of files none of whose thumbnails are cached
+    fn prune_page_counts(&mut self) {
+        let files: HashSet<&FileKey> = self.entries.keys().map(|key| &key.file).collect();
+        self.page_counts.retain(|file, _| files.contains(file));
+    }
+
+    /// Path of the PNG for a thumbnail, writing it out if it is not on disk
+    fn file_for(&mut self, key: &CacheKey, png: &[u8]) -> Result<PathBuf, String> {
+        let file = self.file_path(key);
+        if !self.files.contains_key(key) || !file.exists() {
+            fs::write(&file, png)
+                .map_err(|e| format!("Failed to write thumbnail: {}", e))?;
+        }
+        self.clock += 1;
+        self.files.insert(key.clone(), self.clock);
+        Ok(file)
+    }
+
+    /// Delete the least recently handed out files beyond the limit. Files
+    /// handed out since `since` are kept, as the response naming them has
+    /// not been read yet.
+    fn prune_files(&mut self, since: u64) {
+        while self.files.len() > MAX_FILES {
+            let oldest = self.files.iter()
+                .filter(|(_, &handed_out)| handed_out <= since)
+                .min_by_key(|(_, &handed_out)| handed_out)
+                .map(|(key, _)| key.clone());
+            let Some(key) = oldest else {
+                break;
+            };
+            self.files.remove(&key);
+            let _ = fs::remove_file(self.file_path(&key));
+        }
+    }
+
+    fn file_path(&self, key: &CacheKey) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.png", hasher.finish()))
+    }
+}
+
+/// Render (or fetch from cache) thumbnails for pages of a PDF
+pub fn thumbnails(
+    cache_dir: &Path,
+    path: &str,
+    options: &ThumbnailOptions,
+) -> Result<Vec<PageThumbnail>, String> {
+    let file_path = Path::new(path);
+    let modified = fs::metadata(file_path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|_| format!("File not found: {}", path))?;
+    let file = FileKey {
+        path: file_path.to_path_buf(),
+        modified,
+    };
+    let size = options.size.clamp(MIN_SIZE, MAX_SIZE);
+    let to_file = match options.output.as_str() {
+        "base64" => false,
+        "file" => true,
+        other => return Err(format!("Unsupported thumbnail output: {}", other)),
+    };
+
+    let mut document: Option<render::Document> = None;
+
+    let cached_count = with_cache(cache_dir, |cache| cache.page_counts.get(&file).copied())?;
+    let page_count = match cached_count {
+        Some(count) => count,
+        None => {
+            let doc = render::open(path)?;
+            let count = doc.pages().len() as u32;
+            document = Some(doc);
+            with_cache(cache_dir, |cache| cache.insert_page_count(file.clone(), count))?;
+            count
+        }
+    };
+
+    let pages = options.pages.clone().unwrap_or_else(|| (1..=page_count).collect());
+    if let Some(&page) = pages.iter().find(|&&page| page == 0 || page > page_count) {
+        return Err(format!("Page {} does not exist (document has {} pages)", page, page_count));
+    }
+
+    let key = |page: u32| CacheKey { file: file.clone(), page, size };
+
+    // Render misses without holding the lock, so cached requests stay instant
+    let missing: Vec<u32> = with_cache(cache_dir, |cache| {
+        pages.iter().copied().filter(|&page| !cache.touch(&key(page))).collect()
+    })?;
+
+    let mut rendered = HashMap::with_capacity(missing.len());
+    if !missing.is_empty() {
+        let doc = match document.take() {
+            Some(doc) => doc,
+            None => render::open(path)?,
+        };
+        for &page in &missing {
+            let pdf_page = doc.pages().get((page - 1) as u16)
+                .map_err(|e| format!("Failed to read page: {}", e))?;
+            let image = render::render_thumbnail(&pdf_page, size)?;
+            rendered.insert(page, (Arc::new(encode_png(&image)?), image.width(), image.height()));
+        }
+    }
+
+    with_cache(cache_dir, |cache| {
+        for (&page, (png, width, height)) in &rendered {
+            cache.insert(key(page), png.clone(), *width, *height);
+        }
+
+        // Freshly rendered pages are served from `rendered`, in case a large
+        // request pushed them straight back out of the cache
+        let since = cache.clock;
+        let mut results = Vec::with_capacity(pages.len());
+        for &page in &pages {
+            let key = key(page);
+            let (png, width, height) = match rendered.get(&page) {
+                Some(thumbnail) => thumbnail.clone(),
+                None => match cache.entries.get(&key) {
+                    Some(entry) => (entry.png.clone(), entry.width, entry.height),
+                    None => return Err(format!("Failed to render page {}", page)),
+                },
+            };
+
+            let (data, path) = if to_file {
+                (None, Some(cache.file_for(&key, &png)?.to_string_lossy().to_string()))
+            } else {
+                let encoded = base64::engine::general_purpose::STANDARD.encode(png.as_slice());
+                (Some(format!("data:image/png;base64,{}", encoded)), None)
+            };
+
+            results.push(PageThumbnail {
+                page,
+                width,
+                height,
+                data,
+                path,
+            });
+        }
+
+        cache.prune_files(since);
+        Ok(results)
+    })?
+}
+
+fn with_cache<T>(cache_dir: &Path, f: impl FnOnce(&mut ThumbnailCache) -> T) -> Result<T, String> {
+    let mut guard = CACHE.lock()
+        .map_err(|_| "Thumbnail cache unavailable".to_string())?;
+    let cache = guard.get_or_insert_with(|| ThumbnailCache::new(cache_dir));
+    Ok(f(cache))
+}
+
+fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, String> {
+    let mut png = Vec::new();
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+    Ok(png)
+}
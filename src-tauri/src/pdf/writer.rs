@@ -0,0 +1,235 @@
+use lopdf::{Dictionary, Document, Object, ObjectId, StringFormat};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// Serialize a document with a classic cross-reference table.
+///
+/// lopdf's own writer omits the binary comment after the header line and
+/// keeps cross-reference streams, neither of which suits archival output.
+pub fn save(doc: &Document, path: &Path) -> Result<(), String> {
+    let mut buffer = Vec::new();
+    write_document(doc, &mut buffer);
+    fs::write(path, buffer)
+        .map_err(|e| format!("Failed to write PDF: {}", e))
+}
+
+fn write_document(doc: &Document, out: &mut Vec<u8>) {
+    out.extend_from_slice(format!("%PDF-{}\n", doc.version).as_bytes());
+    out.extend_from_slice(b"%\xE2\xE3\xCF\xD3\n");
+
+    let mut offsets = Vec::new();
+    for (&(id, generation), object) in &doc.objects {
+        if is_structural(object) {
+            continue;
+        }
+        offsets.push((id, generation, out.len()));
+        out.extend_from_slice(format!("{} {} obj\n", id, generation).as_bytes());
+        write_object(out, object);
+        out.extend_from_slice(b"\nendobj\n");
+    }
+
+    let size = offsets.iter().map(|&(id, _, _)| id).max().unwrap_or(0) + 1;
+    let xref_start = out.len();
+    write_xref(out, &offsets, size);
+
+    let mut trailer = Dictionary::new();
+    for key in [&b"Root"[..], b"Info", b"ID", b"Encrypt"] {
+        if let Ok(value) = doc.trailer.get(key) {
+            trailer.set(key, value.clone());
+        }
+    }
+    trailer.set("Size", size as i64);
+
+    out.extend_from_slice(b"trailer\n");
+    write_object(out, &Object::Dictionary(trailer));
+    out.extend_from_slice(format!("\nstartxref\n{}\n%%EOF\n", xref_start).as_bytes());
+}
+
+/// Give the document a file identifier if it has none
+pub fn ensure_file_id(doc: &mut Document) {
+    if doc.trailer.has(b"ID") {
+        return;
+    }
+
+    let mut id = Vec::with_capacity(16);
+    for salt in 0..2u8 {
+        let mut hasher = DefaultHasher::new();
+        salt.hash(&mut hasher);
+        doc.version.hash(&mut hasher);
+        doc.objects.len().hash(&mut hasher);
+        doc.max_id.hash(&mut hasher);
+        chrono::Local::now().timestamp_nanos_opt().hash(&mut hasher);
+        id.extend_from_slice(&hasher.finish().to_be_bytes());
+    }
+
+    doc.trailer.set(
+        "ID",
+        vec![
+            Object::String(id.clone(), StringFormat::Hexadecimal),
+            Object::String(id, StringFormat::Hexadecimal),
+        ],
+    );
+}
+
+/// Drop objects that can no longer be reached from the trailer
+pub fn prune_unreachable(doc: &mut Document) {
+    let mut reachable: HashSet<ObjectId> = HashSet::new();
+    let mut pending: Vec<ObjectId> = Vec::new();
+    collect_references(&Object::Dictionary(doc.trailer.clone()), &mut pending);
+
+    while let Some(id) = pending.pop() {
+        if reachable.insert(id) {
+            if let Some(object) = doc.objects.get(&id) {
+                collect_references(object, &mut pending);
+            }
+        }
+    }
+
+    doc.objects.retain(|id, _| reachable.contains(id));
+}
+
+fn collect_references(object: &Object, references: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => references.push(*id),
+        Object::Array(items) => {
+            for item in items {
+                collect_references(item, references);
+            }
+        }
+        Object::Dictionary(dict) => {
+            for (_, value) in dict.iter() {
+                collect_references(value, references);
+            }
+        }
+        Object::Stream(stream) => {
+            for (_, value) in stream.dict.iter() {
+                collect_references(value, references);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Object streams and xref streams are rebuilt by the writer, never copied.
+fn is_structural(object: &Object) -> bool {
+    matches!(object.type_name(), Ok("ObjStm") | Ok("XRef"))
+}
+
+fn write_xref(out: &mut Vec<u8>, offsets: &[(u32, u16, usize)], size: u32) {
+    let mut entries = vec![None; size as usize];
+    for &(id, generation, offset) in offsets {
+        entries[id as usize] = Some((offset, generation));
+    }
+
+    // Free entries form a linked list starting at object 0
+    let mut next_free = vec![0u32; size as usize];
+    let mut last_free = 0usize;
+    for (id, entry) in entries.iter().enumerate().skip(1) {
+        if entry.is_none() {
+            next_free[last_free] = id as u32;
+            last_free = id;
+        }
+    }
+
+    out.extend_from_slice(format!("xref\n0 {}\n", size).as_bytes());
+    out.extend_from_slice(format!("{:010} 65535 f \n", next_free[0]).as_bytes());
+    for (id, entry) in entries.iter().enumerate().skip(1) {
+        match *entry {
+            Some((offset, generation)) => {
+                out.extend_from_slice(format!("{:010} {:05} n \n", offset, generation).as_bytes());
+            }
+            None => {
+                out.extend_from_slice(format!("{:010} 00001 f \n", next_free[id]).as_bytes());
+            }
+        }
+    }
+}
+
+pub(super) fn write_object(out: &mut Vec<u8>, object: &Object) {
+    match object {
+        Object::Null => out.extend_from_slice(b"null"),
+        Object::Boolean(value) => out.extend_from_slice(if *value { b"true" } else { b"false" }),
+        Object::Integer(value) => out.extend_from_slice(value.to_string().as_bytes()),
+        Object::Real(value) => out.extend_from_slice(format_real(*value).as_bytes()),
+        Object::Name(name) => write_name(out, name),
+        Object::String(bytes, StringFormat::Literal) => write_literal_string(out, bytes),
+        Object::String(bytes, StringFormat::Hexadecimal) => {
+            out.push(b'<');
+            for byte in bytes {
+                out.extend_from_slice(format!("{:02X}", byte).as_bytes());
+            }
+            out.push(b'>');
+        }
+        Object::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b' ');
+                }
+                write_object(out, item);
+            }
+            out.push(b']');
+        }
+        Object::Dictionary(dict) => write_dictionary(out, dict),
+        Object::Stream(stream) => {
+            let mut dict = stream.dict.clone();
+            dict.set("Length", stream.content.len() as i64);
+            write_dictionary(out, &dict);
+            out.extend_from_slice(b"\nstream\n");
+            out.extend_from_slice(&stream.content);
+            out.extend_from_slice(b"\nendstream");
+        }
+        Object::Reference((id, generation)) => {
+            out.extend_from_slice(format!("{} {} R", id, generation).as_bytes());
+        }
+    }
+}
+
+fn write_dictionary(out: &mut Vec<u8>, dict: &Dictionary) {
+    out.extend_from_slice(b"<<");
+    for (key, value) in dict.iter() {
+        write_name(out, key);
+        out.push(b' ');
+        write_object(out, value);
+    }
+    out.extend_from_slice(b">>");
+}
+
+fn write_name(out: &mut Vec<u8>, name: &[u8]) {
+    out.push(b'/');
+    for &byte in name {
+        let delimiter = b"()<>[]{}/%#".contains(&byte);
+        if !(b'!'..=b'~').contains(&byte) || delimiter {
+            out.extend_from_slice(format!("#{:02X}", byte).as_bytes());
+        } else {
+            out.push(byte);
+        }
+    }
+}
+
+fn write_literal_string(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.push(b'(');
+    for &byte in bytes {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                out.push(b'\\');
+                out.push(byte);
+            }
+            b'\r' => out.extend_from_slice(b"\\r"),
+            b'\n' => out.extend_from_slice(b"\\n"),
+            _ => out.push(byte),
+        }
+    }
+    out.push(b')');
+}
+
+fn format_real(value: f32) -> String {
+    if value.fract() == 0.0 {
+        return format!("{}", value as i64);
+    }
+    let formatted = format!("{:.5}", value);
+    formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+}
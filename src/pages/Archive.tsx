@@ -21,7 +21,7 @@ export default function Archive({ config }: Props) {
     const droppedFiles = Array.from(e.dataTransfer.files);
     if (mode === 'extract') {
       const archiveFiles = droppedFiles.filter(f => 
-        /\.(zip|7z|rar)$/i.test(f.name)
+        /\.(zip|7z|rar|[r-z]\d{2}|tar|tgz|tbz2?|txz|tzst|tar\.(?:gz|bz2|xz|zstd?))$/i.test(f.name)
       );
       setFiles(archiveFiles);
     } else {
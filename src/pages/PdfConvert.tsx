@@ -21,6 +21,7 @@ const formats = [
   { id: 'png', name: 'PNG', desc: '高品質画像' },
   { id: 'svg', name: 'SVG', desc: 'ベクター形式' },
   { id: 'webp', name: 'WebP', desc: 'Web最適化' },
+  { id: 'tiff', name: 'TIFF', desc: '印刷・FAX向け' },
 ];
 
 const dpiOptions = [
@@ -30,11 +31,18 @@ const dpiOptions = [
   { value: 600, label: '600 DPI（高品質）' },
 ];
 
+const colorModes = [
+  { id: 'rgb', name: 'カラー' },
+  { id: 'gray', name: 'グレー' },
+  { id: 'mono', name: '白黒' },
+];
+
 export default function PdfConvert({ config }: Props) {
   const navigate = useNavigate();
   const [files, setFiles] = useState<FileItem[]>([]);
   const [format, setFormat] = useState('jpg');
   const [dpi, setDpi] = useState(config.default_pdf_dpi);
+  const [colorMode, setColorMode] = useState('rgb');
   const [pageMode, setPageMode] = useState<'all' | 'select'>('all');
   const [pageRange, setPageRange] = useState('');
   const [isProcessing, setIsProcessing] = useState(false);
@@ -111,7 +119,7 @@ export default function PdfConvert({ config }: Props) {
       } else {
         result = await invoke<ConvertResult>('convert_pdf_to_images', {
           paths,
-          options: { format, dpi, pages },
+          options: { format, dpi, pages, color_mode: colorMode },
         });
       }
       setExportResult(result);
@@ -219,6 +227,18 @@ export default function PdfConvert({ config }: Props) {
               </div>
             )}
 
+            {/* Color mode */}
+            {format !== 'svg' && (
+              <div>
+                <label className="text-xs font-medium text-[var(--text-muted)] uppercase tracking-wide block mb-3">カラー</label>
+                <div className="flex gap-2">
+                  {colorModes.map((m) => (
+                    <button key={m.id} onClick={() => setColorMode(m.id)} className={`flex-1 py-3 rounded-xl text-sm transition-all border ${colorMode === m.id ? 'border-red-400 bg-red-400/10 text-red-400' : 'border-white/5 bg-[var(--bg-tertiary)] hover:border-white/20'}`}>{m.name}</button>
+                  ))}
+                </div>
+              </div>
+            )}
+
             {/* Page selection */}
             <div>
               <label className="text-xs font-medium text-[var(--text-muted)] uppercase tracking-wide block mb-3">ページ選択</label>
@@ -5,6 +5,15 @@ export interface AppConfig {
   default_pdf_dpi: number;
   auto_create_date_folders: boolean;
   cloud_sync_folder: string | null;
+  extraction_limits: ExtractionLimits;
+}
+
+export interface ExtractionLimits {
+  max_total_size: number;
+  max_ratio: number;
+  max_entries: number;
+  max_depth: number;
+  min_free_space: number;
 }
 
 export const defaultConfig: AppConfig = {
@@ -14,6 +23,13 @@ export const defaultConfig: AppConfig = {
   default_pdf_dpi: 150,
   auto_create_date_folders: true,
   cloud_sync_folder: null,
+  extraction_limits: {
+    max_total_size: 20 * 1024 * 1024 * 1024,
+    max_ratio: 1000,
+    max_entries: 100000,
+    max_depth: 64,
+    min_free_space: 1024 * 1024 * 1024,
+  },
 };
 
 export interface ConvertResult {
@@ -29,6 +45,223 @@ export interface PdfInfo {
   file_size: number;
 }
 
+export interface ThumbnailOptions {
+  pages: number[] | null;
+  size: number;
+  output: 'base64' | 'file';
+}
+
+export interface PageThumbnail {
+  page: number;
+  width: number;
+  height: number;
+  data: string | null;
+  path: string | null;
+}
+
+export interface SearchOptions {
+  regex: boolean;
+  case_sensitive: boolean;
+  ignore_width: boolean;
+}
+
+export interface SearchRect {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface SearchHit {
+  page: number;
+  text: string;
+  context: string;
+  rects: SearchRect[];
+  page_width: number;
+  page_height: number;
+}
+
+export interface SearchResult {
+  path: string;
+  hits: SearchHit[];
+  truncated: boolean;
+}
+
+export interface PdfOutputOptions {
+  linearize: boolean;
+}
+
+export interface OverlayOptions {
+  position: 'under' | 'over';
+  first_page_only: boolean;
+  cycle: boolean;
+  scale: 'fit' | 'stretch' | 'none' | null;
+}
+
+export interface OcrOptions {
+  languages: string[];
+  dpi: number | null;
+  pages: number[] | null;
+  force: boolean;
+}
+
+export interface PdfaViolation {
+  rule: string;
+  message: string;
+  page: number | null;
+}
+
+export interface PdfaReport {
+  path: string;
+  conformant: boolean;
+  violations: PdfaViolation[];
+}
+
+export interface PdfFont {
+  name: string;
+  base_font: string;
+  font_type: string;
+  embedded: boolean;
+  subset: boolean;
+  encoding: string | null;
+  pages: number[];
+}
+
+export interface FontReport {
+  path: string;
+  fonts: PdfFont[];
+  not_embedded: number;
+}
+
+export interface PdfSignature {
+  field: string;
+  page: number | null;
+  signed: boolean;
+  sub_filter: string | null;
+  signer_name: string | null;
+  subject: string | null;
+  issuer: string | null;
+  signing_time: string | null;
+  timestamp: string | null;
+  reason: string | null;
+  location: string | null;
+  digest_algorithm: string | null;
+  byte_range: number[];
+  intact: boolean | null;
+  modified_after_signing: boolean;
+}
+
+export interface SignatureReport {
+  path: string;
+  signatures: PdfSignature[];
+  modified: boolean;
+}
+
+export interface CompareOptions {
+  dpi: number;
+  tolerance: number;
+}
+
+export interface PageDiff {
+  page: number;
+  status: 'identical' | 'changed' | 'added' | 'removed';
+  changed_percent: number;
+  text_added: string[];
+  text_removed: string[];
+  diff_image: string | null;
+}
+
+export interface CompareResult extends ConvertResult {
+  pages: PageDiff[];
+}
+
+export interface PdfLink {
+  page: number;
+  kind: 'internal' | 'uri' | 'file' | 'named' | 'javascript' | 'other';
+  target: string;
+  dest_page: number | null;
+  rect: [number, number, number, number] | null;
+  broken: boolean;
+}
+
+export interface OutlineEntry {
+  level: number;
+  title: string;
+  kind: PdfLink['kind'] | null;
+  target: string | null;
+  dest_page: number | null;
+  broken: boolean;
+}
+
+export interface LinkReport {
+  path: string;
+  links: PdfLink[];
+  outline: OutlineEntry[];
+  broken: number;
+}
+
+export interface LinkReportResult extends ConvertResult {
+  reports: LinkReport[];
+}
+
+export interface ArchiveOptions {
+  format: 'zip' | '7z' | 'tar' | 'tar.gz' | 'tar.bz2' | 'tar.xz' | 'tar.zst' | 'tar.lz4';
+  password: string | null;
+  level: number | null;
+  solid: boolean | null;
+  encrypt_names: boolean;
+  include: string[];
+  exclude: string[];
+}
+
+export interface CompressOptions {
+  format: 'gz' | 'bz2' | 'xz' | 'zst' | 'lz4';
+  level: number | null;
+  threads: number | null;
+}
+
+export interface ExtractOptions {
+  entries: string[];
+  flatten: boolean;
+}
+
+export interface ArchiveEntry {
+  path: string;
+  is_dir: boolean;
+  size: number;
+  compressed_size: number | null;
+  modified: string | null;
+  encrypted: boolean;
+  crc: number | null;
+  method: string | null;
+}
+
+export interface ArchiveListing {
+  path: string;
+  format: string;
+  entries: ArchiveEntry[];
+  total_size: number;
+  file_count: number;
+}
+
+export interface ArchiveEntryTest {
+  path: string;
+  size: number;
+  encrypted: boolean;
+  passed: boolean;
+  error: string | null;
+}
+
+export interface ArchiveTestReport {
+  path: string;
+  format: string;
+  entries: ArchiveEntryTest[];
+  passed: number;
+  failed: number;
+  error: string | null;
+  intact: boolean;
+}
+
 export interface VideoInfo {
   path: string;
   duration_seconds: number;